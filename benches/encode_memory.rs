@@ -0,0 +1,138 @@
+// Memory allocated to encode a large set-add, the message streamed into the writer by the coder versus serialized into
+// an intermediate Vec first (how messages were encoded before). Counts the bytes allocated through the global allocator
+// while encoding, reported by criterion in place of the time, so a run compares both against the previous one.
+// Run with `cargo bench --bench encode_memory`.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use byteorder::{BigEndian, ByteOrder};
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use protobuf::{Message, RepeatedField};
+
+use antidote_rust_client::antidote_pb::*;
+
+// Bytes allocated since the start of the bench.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Bytes allocated by the benchmarked routine.
+struct Allocated;
+
+impl Measurement for Allocated {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATED.load(Ordering::Relaxed)
+    }
+    fn end(&self, start: usize) -> usize {
+        ALLOCATED.load(Ordering::Relaxed) - start
+    }
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+    fn zero(&self) -> usize {
+        0
+    }
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &BytesFormatter
+    }
+}
+
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = if typical_value < 1024.0 {
+            (1.0, "B")
+        } else if typical_value < 1024.0 * 1024.0 {
+            (1024.0, "KiB")
+        } else {
+            (1024.0 * 1024.0, "MiB")
+        };
+        for v in values {
+            *v /= factor;
+        }
+        unit
+    }
+
+    fn scale_throughputs(&self, _typical_value: f64, _throughput: &Throughput, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
+// Update of a single transaction adding `size` elements of 16 bytes to a set.
+fn set_add(size: usize) -> ApbUpdateObjects {
+    let mut add = ApbSetUpdate::new();
+    add.set_optype(ApbSetUpdate_SetOpType::ADD);
+    add.set_adds(RepeatedField::from_vec((0..size).map(|i| format!("element{:08}", i).into_bytes()).collect()));
+    let mut op = ApbUpdateOp::new();
+    op.mut_boundobject().set_key("set".as_bytes().to_vec());
+    op.mut_boundobject().set_field_type(CRDT_type::ORSET);
+    op.mut_boundobject().set_bucket("bucket".as_bytes().to_vec());
+    op.mut_operation().set_setop(add);
+    let mut update = ApbUpdateObjects::new();
+    update.mut_updates().push(op);
+    update.set_transaction_descriptor("descriptor".as_bytes().to_vec());
+    update
+}
+
+// The frame as it was written before the coder streamed messages: serialized into a Vec, then header and body.
+fn encode_buffered(message: &dyn Message, msg_code: u8, writer: &mut dyn Write) -> io::Result<()> {
+    let body = message.write_to_bytes()?;
+    let mut header = [0u8; 5];
+    BigEndian::write_u32(&mut header[0..4], body.len() as u32 + 1);
+    header[4] = msg_code;
+    writer.write_all(&header)?;
+    writer.write_all(&body)
+}
+
+fn large_set_add(c: &mut Criterion<Allocated>) {
+    let mut group = c.benchmark_group("large_set_add_allocated");
+    for size in [1000usize, 100000] {
+        let update = set_add(size);
+        group.bench_with_input(BenchmarkId::new("streamed", size), &update, |b, update| b.iter(|| {
+            update.encode(&mut io::sink()).unwrap()
+        }));
+        group.bench_with_input(BenchmarkId::new("buffered", size), &update, |b, update| b.iter(|| {
+            encode_buffered(update, 118, &mut io::sink()).unwrap()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_measurement(Allocated);
+    targets = large_set_add
+}
+criterion_main!(benches);
@@ -0,0 +1,198 @@
+// Benchmarks of the client's hot paths against the in-memory transport and the fake server of the tests, no Antidote server required.
+// Run with `cargo bench`, criterion keeps the results of the previous run in target/criterion to compare against.
+use std::sync::Arc;
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use protobuf::Message;
+
+use antidote_rust_client::antidote_pb::*;
+use antidote_rust_client::coder;
+use antidote_rust_client::transactions::*;
+use antidote_rust_client::{AntidoteConnectionManager, Client, ClientBuilder, Connector, Host, SharedClient, Transport};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+use common::{FakeAntidote, MemoryTransport, write_frame};
+
+// Client with a single pooled connection to a MemoryTransport.
+fn memory_client() -> Client {
+    let connector: Connector = Arc::new(|_addr: &str| Ok(Box::new(MemoryTransport::new()) as Box<dyn Transport>));
+    let manager = AntidoteConnectionManager::with_connector(String::from("memory:1"), connector);
+    let pool = r2d2::Pool::builder().max_size(1).build(manager).unwrap();
+    Client::with_existing_pools(vec!(Host::new("memory", 1)), vec!(pool)).unwrap()
+}
+
+// Client whose pool of the given size connects to MemoryTransports.
+fn memory_client_with_pool(max_pool_size: u32) -> Client {
+    let connector: Connector = Arc::new(|_addr: &str| Ok(Box::new(MemoryTransport::new()) as Box<dyn Transport>));
+    ClientBuilder::new()
+        .host(Host::new("memory", 1))
+        .connector(connector)
+        .max_pool_size(max_pool_size)
+        .build()
+        .unwrap()
+}
+
+fn counter_increment(c: &mut Criterion) {
+    let client = memory_client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut group = c.benchmark_group("counter_increment");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("interactive", |b| b.iter(|| {
+        let mut tx = client.start_transaction().unwrap();
+        bucket.update(&mut tx, vec!(counter_inc(&key, 1))).unwrap();
+        tx.commit().unwrap();
+    }));
+    group.bench_function("static", |b| b.iter(|| {
+        let mut tx = client.create_static_transaction().unwrap();
+        bucket.update(&mut tx, vec!(counter_inc(&key, 1))).unwrap();
+    }));
+    group.finish();
+}
+
+// 100 increments in one interactive transaction, each awaiting its response or not (`update_no_ack`).
+fn bulk_update(c: &mut Criterion) {
+    let client = memory_client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut group = c.benchmark_group("bulk_update");
+    group.throughput(Throughput::Elements(100));
+    for no_ack in [false, true] {
+        let name = if no_ack { "no_ack" } else { "ack" };
+        group.bench_function(name, |b| b.iter(|| {
+            let mut tx = client.transaction_builder().update_no_ack(no_ack).start().unwrap();
+            for _ in 0..100 {
+                bucket.update(&mut tx, vec!(counter_inc(&key, 1))).unwrap();
+            }
+            tx.commit().unwrap();
+        }));
+    }
+    group.finish();
+}
+
+// 8 threads doing 50 transactions each, on a pool with fewer connections than threads and on one sized for the threads.
+// r2d2 has no fair (FIFO) checkout mode, waiting threads are woken in no particular order; the gap between both
+// shows what contention for connections costs and why the pool should be sized for the concurrent threads.
+fn contended_checkout(c: &mut Criterion) {
+    const THREADS: usize = 8;
+    let bucket = Arc::new(Bucket::new("bucket".as_bytes().to_vec()));
+    let key = Arc::new(Key("counter".as_bytes().to_vec()));
+
+    let mut group = c.benchmark_group("contended_checkout");
+    group.throughput(Throughput::Elements(THREADS as u64 * 50));
+    for pool_size in [2u32, THREADS as u32] {
+        let client = SharedClient::new(memory_client_with_pool(pool_size));
+        group.bench_with_input(BenchmarkId::new("pool_size", pool_size), &client, |b, client| b.iter(|| {
+            let threads: Vec<_> = (0..THREADS).map(|_| {
+                let (client, bucket, key) = (client.clone(), bucket.clone(), key.clone());
+                thread::spawn(move || for _ in 0..50 {
+                    let mut tx = client.start_transaction().unwrap();
+                    bucket.update(&mut tx, vec!(counter_inc(&*key, 1))).unwrap();
+                    tx.commit().unwrap();
+                })
+            }).collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+        }));
+    }
+    group.finish();
+}
+
+// Large set-adds (20000 elements, about 400 KiB per update) over TCP to the fake server of the tests,
+// with the OS default socket buffers and with 1 MiB buffers (ClientBuilder::send_buffer_size / recv_buffer_size).
+fn socket_buffers(c: &mut Criterion) {
+    let server = FakeAntidote::start(None);
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("set".as_bytes().to_vec());
+    let elems: Vec<Vec<u8>> = (0..20000).map(|i| format!("element{:08}", i).into_bytes()).collect();
+
+    let mut group = c.benchmark_group("socket_buffers");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(elems.len() as u64));
+    for buffer_size in [None, Some(1usize << 20)] {
+        let mut builder = ClientBuilder::new().host(Host::new("127.0.0.1", server.port)).max_pool_size(1);
+        if let Some(size) = buffer_size {
+            builder = builder.send_buffer_size(size).recv_buffer_size(size);
+        }
+        let client = builder.build().unwrap();
+        let name = if buffer_size.is_some() { "1MiB" } else { "os_default" };
+        group.bench_function(name, |b| b.iter(|| {
+            let mut tx = client.start_transaction().unwrap();
+            bucket.update(&mut tx, vec!(set_add(&key, elems.clone()))).unwrap();
+            tx.commit().unwrap();
+        }));
+    }
+    group.finish();
+}
+
+fn batch_read(c: &mut Criterion) {
+    let client = memory_client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+
+    let mut group = c.benchmark_group("batch_read");
+    for size in [1usize, 16, 128] {
+        let read = (0..size).fold(MultiRead::new(), |read, i| {
+            read.add(&bucket, &Key(format!("counter{}", i).into_bytes()), CRDT_type::COUNTER)
+        });
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &read, |b, read| b.iter(|| {
+            let mut tx = client.start_transaction().unwrap();
+            read.read(&mut tx).unwrap();
+            tx.commit().unwrap();
+        }));
+    }
+    group.finish();
+}
+
+// Read response frame of a map with `size` counters and `size` registers.
+fn map_response(size: usize) -> Vec<u8> {
+    let mut map = ApbGetMapResp::new();
+    for i in 0..size {
+        let mut counter = ApbMapEntry::new();
+        counter.mut_key().set_key(format!("counter{}", i).into_bytes());
+        counter.mut_key().set_field_type(CRDT_type::COUNTER);
+        counter.mut_value().mut_counter().set_value(i as i32);
+        map.mut_entries().push(counter);
+        let mut reg = ApbMapEntry::new();
+        reg.mut_key().set_key(format!("reg{}", i).into_bytes());
+        reg.mut_key().set_field_type(CRDT_type::LWWREG);
+        reg.mut_value().mut_reg().set_value(vec![0u8; 32]);
+        map.mut_entries().push(reg);
+    }
+    let mut object = ApbReadObjectResp::new();
+    object.set_map(map);
+    let mut resp = ApbReadObjectsResp::new();
+    resp.set_success(true);
+    resp.mut_objects().push(object);
+    let mut frame = Vec::new();
+    write_frame(&mut frame, 126, &resp.write_to_bytes().unwrap()).unwrap();
+    frame
+}
+
+fn map_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("map_read");
+    for size in [8usize, 64, 512] {
+        let frame = map_response(size);
+        let wanted: Vec<(Key, CRDT_type)> = (0..size).step_by(4)
+            .map(|i| (Key(format!("counter{}", i).into_bytes()), CRDT_type::COUNTER))
+            .collect();
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_with_input(BenchmarkId::new("decode", size), &frame, |b, frame| b.iter(|| {
+            coder::decode_read_objects_resp(&mut frame.as_slice()).unwrap()
+        }));
+        group.bench_with_input(BenchmarkId::new("decode_extract", size), &frame, |b, frame| b.iter(|| {
+            let mut resp = coder::decode_read_objects_resp(&mut frame.as_slice()).unwrap();
+            let map = MapReadResult { map_resp: resp.mut_objects()[0].take_map() };
+            map.get_many(&wanted)
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, counter_increment, bulk_update, contended_checkout, socket_buffers, batch_read, map_read);
+criterion_main!(benches);
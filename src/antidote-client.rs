@@ -4,129 +4,936 @@
 /// - at least randomize pools when getting a connection
 /// - privacy for struct and functions
 
+#[cfg(feature = "net")]
 extern crate r2d2;
 // extern crate scheduled_thread_pool;
 
+#[cfg(feature = "net")]
+use std::convert::TryFrom;
+#[cfg(feature = "net")]
 use std::io::{Error, ErrorKind};
+#[cfg(feature = "net")]
+use std::sync::{Arc, Mutex, RwLock};
+#[cfg(feature = "net")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "net")]
+use std::time::{Duration, Instant};
 // use rand::{thread_rng, Rng};
 
 // inline code from other modules
+// the protocol layer (antidote_pb, coder) works on any Read/Write, everything using the network is behind the feature "net"
+#[cfg(feature = "net")]
 pub mod transactions;
 pub mod antidote_pb; // generated pb file
+pub mod observer;
+pub mod error;
+#[cfg(feature = "net")]
 mod r2d2_adapter;
-mod coder;
+#[cfg(feature = "net")]
+mod limit;
+#[cfg(feature = "net")]
+mod descriptor;
+pub mod coder;
 
 // better access to transactions
-use transactions::{InteractiveTransaction, StaticTransaction};
-use r2d2_adapter::{AntidoteConnectionManager};
+#[cfg(feature = "net")]
+use transactions::{CRDTValue, InteractiveTransaction, MultiRead, StaticTransaction, TransactionBuilder};
+#[cfg(feature = "net")]
+pub use r2d2_adapter::{AntidoteConnectionManager, Connection, Connector, Transport};
+#[cfg(feature = "net")]
+use r2d2_adapter::{HostHealth, SocketOptions};
+#[cfg(feature = "net")]
+pub use limit::TransactionLimitMode;
+#[cfg(feature = "net")]
+use limit::TransactionLimit;
+#[cfg(feature = "net")]
+pub use descriptor::{NodeStatus, TransactionDescriptor};
+#[cfg(feature = "net")]
+use observer::{ConnectionEventListener, LatencyObserver, Operation};
+#[cfg(feature = "net")]
+use error::{AntidoteError, ErrorCode};
 
 
 // constants
 // const INITIAL_POOL_SIZE: usize = 5;
+#[cfg(feature = "net")]
 const MAX_POOL_SIZE: usize = 50;
-const CONNECT_RETRY_PERIOD: u64 = 1000; // if connection is refused retry after every 1 sec
+#[cfg(feature = "net")]
+const CONNECTION_TIMEOUT: u64 = 30000; // r2d2 default: wait up to 30 sec for a pooled connection
+#[cfg(feature = "net")]
+const CHECKOUT_DEADLINE: u64 = 30000; // try all hosts for up to 30 sec before giving up
+#[cfg(feature = "net")]
+const TCP_CONNECT_TIMEOUT: u64 = 5000; // give up connecting to an unreachable host after 5 sec
+#[cfg(feature = "net")]
+const FAILURE_THRESHOLD: u32 = 5; // consecutive failures before a host is quarantined
+#[cfg(feature = "net")]
+const QUARANTINE_PERIOD: u64 = 10000; // skip a quarantined host for 10 sec
 
 // Represents connections to the Antidote database.
+#[cfg(feature = "net")]
 pub struct Client {
+    // swapped as a whole by replace_host and reconnect_all, a checkout works on the hosts it started with
+    host_pools: RwLock<Arc<HostPools>>,
+    socket_options: SocketOptions,
+    observer: Option<Arc<dyn LatencyObserver>>,
+    connection_listener: Option<Arc<dyn ConnectionEventListener>>,
+    connector: Option<Connector>,
+    checkout_deadline: Duration,
+    connection_descriptor: Mutex<Option<Vec<u8>>>,
+    transaction_limit: Option<Arc<TransactionLimit>>,
+    single_use: bool,
+    exhausted_mode: PoolExhaustedMode,
+    host_selection: HostSelection,
+    // number of checkouts so far, the start of the next one with HostSelection::RoundRobin
+    checkouts: AtomicUsize,
+}
+
+// The hosts of a client with their pools and health, at the same index.
+#[cfg(feature = "net")]
+#[derive(Clone)]
+struct HostPools {
+    hosts: Vec<Host>,
     pools: Vec<r2d2::Pool<AntidoteConnectionManager>>,
+    health: Vec<Arc<HostHealth>>,
+    // pools of temporary connections per host for PoolExhaustedMode::Overflow
+    overflow: Vec<Option<r2d2::Pool<AntidoteConnectionManager>>>,
 }
 
 // Represents an Antidote server.
 // The port needs to be the port of the protocol-buffer interface (usually 8087)
+#[derive(Debug, Clone)]
 pub struct Host {
     pub name: String,
-    pub port: i32,
+    pub port: u16,
+    // Public like name and port, so struct literals need to set it or end with `..Default::default()`.
+    pub role: HostRole,
+}
+
+// Which traffic a host should preferably serve.
+// Reads (read-only transactions and static reads) go to ReadPreferred hosts first, everything else to Any hosts first;
+// either falls back to the other hosts if none of the preferred ones is healthy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HostRole {
+    #[default]
+    Any,
+    ReadPreferred,
+}
+
+// What checking out a connection (to start a transaction or run a static operation) does when all connections
+// of a host's pool are in use.
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PoolExhaustedMode {
+    // Wait for a connection to be returned, at most for the connection timeout (and within the checkout deadline).
+    #[default]
+    Block,
+    // Try the next host right away, fail with AntidoteError::PoolExhausted if the pools of all hosts are exhausted.
+    FailFast,
+    // Open up to the given number of temporary connections per host beyond the pool size, closed after use.
+    // Waits like Block once they are in use as well.
+    Overflow(u32),
+}
+
+// In which order a checkout tries the hosts (within the hosts of the preferred role, see HostRole).
+// Both orders are deterministic, so tests can assert how traffic is distributed across hosts.
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HostSelection {
+    // Always the first healthy host in the configured order, the others serve as fallbacks.
+    #[default]
+    InOrder,
+    // Each checkout starts one host further in the configured order, spreading the connections evenly.
+    RoundRobin,
+}
+
+impl Host {
+    pub fn new(name: &str, port: u16) -> Host {
+        Host { name: String::from(name), port, role: HostRole::Any }
+    }
+
+    pub fn with_role(mut self, role: HostRole) -> Host {
+        self.role = role;
+        self
+    }
+}
+
+// localhost:8087, Antidote's default protocol-buffer port, e.g. `Host { port: 8101, ..Default::default() }`.
+impl Default for Host {
+    fn default() -> Host {
+        Host::new("localhost", 8087)
+    }
+}
+
+#[cfg(feature = "net")]
+impl Host {
+    // Transitional constructor for ports kept as i32, the type of `port` before it became u16.
+    // Fails with InvalidInput for a port outside 1..65535 instead of building an address that cannot be connected to.
+    #[deprecated(note = "the port of a host is a u16 now, use Host::new")]
+    pub fn from_i32_port(name: &str, port: i32) -> Result<Host, AntidoteError> {
+        match u16::try_from(port) {
+            Ok(port) if port > 0 => Ok(Host::new(name, port)),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("Invalid port {} of host {}, expected 1..65535", port, name)).into()),
+        }
+    }
+
+    fn address(&self) -> String {
+        self.name.clone()+":"+&self.port.to_string()
+    }
 }
 
 // Recreates a new Antidote client connected to the given Antidote servers.
-pub fn new_client(hosts: Vec<Host>) -> Result<Client, Error> {
-    let mut pools = Vec::new();
-    for h in hosts.iter() {
-        let addr : String = h.name.clone()+":"+&h.port.clone().to_string();
+#[cfg(feature = "net")]
+pub fn new_client(hosts: Vec<Host>) -> Result<Client, AntidoteError> {
+    ClientBuilder::new().hosts(hosts).build()
+}
+
+// Configures the connection pools of a client before connecting to the Antidote servers.
+// Defaults: max_pool_size = 50, min_idle = max_pool_size, connection_timeout = 30 sec, test_on_check_out = true,
+// checkout_deadline = 30 sec, connect_timeout = 5 sec, failure_threshold = 5, quarantine_period = 10 sec, no limit of open transactions,
+// pool_exhausted_mode = Block,
+// OS default socket buffer sizes, no latency observer (with feature "metrics": observer::MetricsObserver),
+// no connection event listener.
+//
+// Building a client does not wait for the hosts, connections are established in the background.
+// If no host is reachable the first operation fails with AntidoteError::NoHealthyHosts after the checkout deadline.
+//
+// Note on fairness: r2d2 does not offer a fair (FIFO) checkout mode, threads waiting for a connection
+// of an exhausted pool are woken up in no particular order. To get predictable checkout latencies
+// size the pool for the number of concurrent threads and bound the waiting time with connection_timeout
+// (benchmark: contended_checkout in benches/hot_paths.rs).
+#[cfg(feature = "net")]
+pub struct ClientBuilder {
+    hosts: Vec<Host>,
+    max_pool_size: u32,
+    max_total_connections: Option<u32>,
+    min_idle: Option<u32>,
+    connection_timeout: Duration,
+    test_on_check_out: bool,
+    checkout_deadline: Duration,
+    failure_threshold: u32,
+    quarantine_period: Duration,
+    socket_options: SocketOptions,
+    observer: Option<Arc<dyn LatencyObserver>>,
+    connection_listener: Option<Arc<dyn ConnectionEventListener>>,
+    connector: Option<Connector>,
+    max_open_transactions: Option<usize>,
+    transaction_limit_mode: TransactionLimitMode,
+    single_use: bool,
+    exhausted_mode: PoolExhaustedMode,
+    host_selection: HostSelection,
+}
+
+#[cfg(feature = "net")]
+impl Default for ClientBuilder {
+    fn default() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+}
+
+#[cfg(feature = "net")]
+impl ClientBuilder {
+    pub fn new() -> ClientBuilder {
+        ClientBuilder {
+            hosts: Vec::new(),
+            max_pool_size: MAX_POOL_SIZE as u32,
+            max_total_connections: None,
+            min_idle: None,
+            connection_timeout: Duration::from_millis(CONNECTION_TIMEOUT),
+            test_on_check_out: true,
+            checkout_deadline: Duration::from_millis(CHECKOUT_DEADLINE),
+            failure_threshold: FAILURE_THRESHOLD,
+            quarantine_period: Duration::from_millis(QUARANTINE_PERIOD),
+            socket_options: SocketOptions {
+                connect_timeout: Some(Duration::from_millis(TCP_CONNECT_TIMEOUT)),
+                ..SocketOptions::default()
+            },
+            observer: default_observer(),
+            connection_listener: None,
+            connector: None,
+            max_open_transactions: None,
+            transaction_limit_mode: TransactionLimitMode::Block,
+            single_use: false,
+            exhausted_mode: PoolExhaustedMode::Block,
+            host_selection: HostSelection::InOrder,
+        }
+    }
+
+    // Adds an Antidote server to connect to.
+    pub fn host(mut self, host: Host) -> ClientBuilder {
+        self.hosts.push(host);
+        self
+    }
+
+    // Adds several Antidote servers to connect to.
+    pub fn hosts(mut self, hosts: Vec<Host>) -> ClientBuilder {
+        self.hosts.extend(hosts);
+        self
+    }
+
+    // Maximum number of connections per host.
+    pub fn max_pool_size(mut self, max_pool_size: u32) -> ClientBuilder {
+        self.max_pool_size = max_pool_size;
+        self
+    }
+
+    // Budget of connections across all hosts, e.g. to stay below the file descriptor limit on large clusters.
+    // Each host gets max_total_connections / number of hosts connections at most: its pool up to max_pool_size,
+    // the overflow connections of PoolExhaustedMode::Overflow out of what is left of its share.
+    // min_idle is lowered to the resulting pool size, building fails if the budget is smaller than the number of hosts.
+    pub fn max_total_connections(mut self, max_total_connections: u32) -> ClientBuilder {
+        self.max_total_connections = Some(max_total_connections);
+        self
+    }
+
+    // Number of idle connections each pool tries to keep open; None keeps max_pool_size connections open.
+    // At most the pool size, a larger value is lowered to it.
+    pub fn min_idle(mut self, min_idle: Option<u32>) -> ClientBuilder {
+        self.min_idle = min_idle;
+        self
+    }
+
+    // Disables pooling for short-lived tools doing a few operations: no connections are opened in advance,
+    // each transaction (or static operation) opens a connection and closes it when it is done.
+    // max_pool_size still limits the connections open at once per host; min_idle is ignored.
+    pub fn no_pool(mut self) -> ClientBuilder {
+        self.single_use = true;
+        self
+    }
+
+    // In which order checkouts try the hosts, e.g. RoundRobin to spread the load over all hosts.
+    pub fn host_selection(mut self, selection: HostSelection) -> ClientBuilder {
+        self.host_selection = selection;
+        self
+    }
+
+    // What a checkout does when all connections of a host's pool are in use, e.g. FailFast for latency-sensitive
+    // request paths that rather shed load than queue. Overflow connections are only opened for pools built by the builder.
+    pub fn pool_exhausted_mode(mut self, mode: PoolExhaustedMode) -> ClientBuilder {
+        self.exhausted_mode = mode;
+        self
+    }
+
+    // How long a checkout waits at most for a connection of a host's pool, e.g. when all its connections are in use,
+    // before giving up on the host and trying the next one. The checkout deadline bounds the wait across all hosts,
+    // each host gets at most its share of the remaining deadline.
+    pub fn connection_timeout(mut self, connection_timeout: Duration) -> ClientBuilder {
+        self.connection_timeout = connection_timeout;
+        self
+    }
+
+    // Whether a connection is validated each time it is checked out of a pool.
+    pub fn test_on_check_out(mut self, test_on_check_out: bool) -> ClientBuilder {
+        self.test_on_check_out = test_on_check_out;
+        self
+    }
+
+    // Total time to try all hosts for a connection before failing with AntidoteError::NoHealthyHosts.
+    pub fn checkout_deadline(mut self, checkout_deadline: Duration) -> ClientBuilder {
+        self.checkout_deadline = checkout_deadline;
+        self
+    }
+
+    // Number of consecutive failures (failed connects or broken connections) after which a host is quarantined:
+    // get_connection skips it for quarantine_period, afterwards it is tried again and a single failure quarantines it again.
+    // Meanwhile the pool keeps reconnecting in the background to refill its min_idle connections, a successful connect
+    // reinstates the host early; pools without idle connections (min_idle Some(0), no_pool) wait for the period to end.
+    // 0 disables the quarantine.
+    pub fn failure_threshold(mut self, failure_threshold: u32) -> ClientBuilder {
+        self.failure_threshold = failure_threshold;
+        self
+    }
+
+    // How long a quarantined host is skipped.
+    pub fn quarantine_period(mut self, quarantine_period: Duration) -> ClientBuilder {
+        self.quarantine_period = quarantine_period;
+        self
+    }
+
+    // How long establishing a TCP connection may take, so a connect to a down host fails fast instead of waiting
+    // for the OS timeout and the pool retries (or the next host is tried) promptly.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> ClientBuilder {
+        self.socket_options.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    // Size of the socket send buffer (SO_SNDBUF) of new connections, e.g. 1 MiB for bulk loads.
+    // The OS may clamp the requested size (on Linux to net.core.wmem_max, and it doubles the value for bookkeeping).
+    pub fn send_buffer_size(mut self, size: usize) -> ClientBuilder {
+        self.socket_options.send_buffer_size = Some(size);
+        self
+    }
+
+    // Size of the socket receive buffer (SO_RCVBUF) of new connections, clamped by the OS like send_buffer_size
+    // (on Linux to net.core.rmem_max).
+    pub fn recv_buffer_size(mut self, size: usize) -> ClientBuilder {
+        self.socket_options.recv_buffer_size = Some(size);
+        self
+    }
+
+    // Maximum number of interactive transactions open at once, to protect Antidote from overload.
+    // Unlike the pool size this also holds back threads that would get a connection of another host.
+    // A transaction counts from its start until it is committed, aborted or dropped.
+    pub fn max_open_transactions(mut self, max_open_transactions: usize) -> ClientBuilder {
+        self.max_open_transactions = Some(max_open_transactions);
+        self
+    }
+
+    // Whether starting a transaction beyond max_open_transactions waits (up to the checkout deadline) or fails right away.
+    pub fn transaction_limit_mode(mut self, mode: TransactionLimitMode) -> ClientBuilder {
+        self.transaction_limit_mode = mode;
+        self
+    }
+
+    // Observer notified about the duration of every operation of the client.
+    pub fn latency_observer(mut self, observer: Arc<dyn LatencyObserver>) -> ClientBuilder {
+        self.observer = Some(observer);
+        self
+    }
+
+    // Opens the connections of the pools with the given connector instead of a TCP connect, e.g. to wrap the stream in TLS,
+    // go through a SOCKS proxy or connect to a Unix socket. The connector gets the 'host:port' address of each host,
+    // the socket options of the builder (timeouts, buffer sizes, keepalive) are then up to it.
+    // Pools created later by replace_host use it as well.
+    pub fn connector(mut self, connector: Connector) -> ClientBuilder {
+        self.connector = Some(connector);
+        self
+    }
+
+    // Listener notified when pooled connections are created, checked out, returned and discarded.
+    // Not applied to pre-built pools (build_with_pools), see AntidoteConnectionManager::with_event_listener.
+    pub fn connection_event_listener(mut self, listener: Arc<dyn ConnectionEventListener>) -> ClientBuilder {
+        self.connection_listener = Some(listener);
+        self
+    }
+
+    // Fails with InvalidInput for a host with port 0 or a host given twice, for a connection_timeout or
+    // checkout_deadline too large to be added to the clock, and with AntidoteError::NoHostsConfigured
+    // without hosts, before any pool is built.
+    pub fn build(self) -> Result<Client, AntidoteError> {
+        self.validate_hosts()?;
+        self.validate_timeouts()?;
+        let mut max_pool_size = self.max_pool_size;
+        let mut exhausted_mode = self.exhausted_mode;
+        if let Some(total) = self.max_total_connections {
+            let per_host = total / self.hosts.len() as u32;
+            if per_host == 0 {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("A budget of {} connections is too small for {} hosts", total, self.hosts.len())).into())
+            }
+            max_pool_size = max_pool_size.min(per_host);
+            if let PoolExhaustedMode::Overflow(max) = exhausted_mode {
+                exhausted_mode = PoolExhaustedMode::Overflow(max.min(per_host - max_pool_size));
+            }
+        }
+        let settings = PoolSettings {
+            max_size: max_pool_size,
+            // r2d2 panics on a min_idle above the pool size
+            min_idle: if self.single_use { Some(0) } else { self.min_idle.map(|min_idle| min_idle.min(max_pool_size)) },
+            connection_timeout: self.connection_timeout,
+            test_on_check_out: self.test_on_check_out,
+            single_use: self.single_use,
+        };
+        let mut pools = Vec::new();
+        let mut health = Vec::new();
+        let mut overflow = Vec::new();
+        for h in self.hosts.iter() {
+            let host_health = Arc::new(HostHealth::new(self.failure_threshold, self.quarantine_period));
+            let hooks = ManagerHooks { connector: &self.connector, listener: &self.connection_listener };
+            pools.push(build_pool(h, host_health.clone(), self.socket_options, settings, hooks));
+            overflow.push(build_overflow_pool(h, host_health.clone(), self.socket_options, settings, hooks, exhausted_mode));
+            health.push(host_health);
+        }
+        self.build_client(pools, health, overflow)
+    }
+
+    // Builds the client around pre-built pools, one per host in the same order, instead of creating them.
+    // The pool settings of the builder are ignored. Used to inject a connection manager with a custom Connector,
+    // e.g. an in-memory stream in tests; prefer ClientBuilder::connector, which keeps the pool settings.
+    // Note that replace_host creates a TCP pool (or one with the builder's connector)
+    // and that hosts of pre-built pools are never quarantined.
+    pub fn build_with_pools(self, pools: Vec<r2d2::Pool<AntidoteConnectionManager>>) -> Result<Client, AntidoteError> {
+        self.validate_hosts()?;
+        if pools.len() != self.hosts.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Got {} pools for {} hosts", pools.len(), self.hosts.len())).into())
+        }
+        let health = pools.iter().map(|_| Arc::new(HostHealth::new(self.failure_threshold, self.quarantine_period))).collect();
+        let overflow = pools.iter().map(|_| None).collect();
+        self.build_client(pools, health, overflow)
+    }
+
+    // All timing uses the monotonic Instant, never the wall clock; a deadline beyond the range of Instant
+    // would panic when r2d2 computes it on checkout (e.g. Duration::MAX for "forever").
+    fn validate_timeouts(&self) -> Result<(), AntidoteError> {
+        let now = Instant::now();
+        for (name, timeout) in [("connection_timeout", self.connection_timeout), ("checkout_deadline", self.checkout_deadline)] {
+            if now.checked_add(timeout).is_none() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("{} of {:?} is too large", name, timeout)).into())
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_hosts(&self) -> Result<(), AntidoteError> {
+        if self.hosts.is_empty() {
+            return Err(AntidoteError::NoHostsConfigured)
+        }
+        for (i, h) in self.hosts.iter().enumerate() {
+            validate_host(h)?;
+            if self.hosts[..i].iter().any(|other| other.name == h.name && other.port == h.port) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Host {} is configured more than once", h.address())).into())
+            }
+        }
+        Ok(())
+    }
 
-        let connection_manager = AntidoteConnectionManager::new(addr);
-        let pool: r2d2::Pool<AntidoteConnectionManager> = r2d2::Pool::builder()
-            .max_size(MAX_POOL_SIZE as u32)
-            .build(connection_manager)
-            .unwrap();
-        pools.push(pool);
+    fn build_client(self, pools: Vec<r2d2::Pool<AntidoteConnectionManager>>, health: Vec<Arc<HostHealth>>,
+            overflow: Vec<Option<r2d2::Pool<AntidoteConnectionManager>>>) -> Result<Client, AntidoteError> {
+        if self.max_open_transactions == Some(0) {
+            return Err(Error::new(ErrorKind::InvalidInput, "The limit of open transactions must be at least 1").into())
+        }
+        let mode = self.transaction_limit_mode;
+        let client = Client {
+            host_pools: RwLock::new(Arc::new(HostPools { hosts: self.hosts, pools, health, overflow })),
+            socket_options: self.socket_options,
+            observer: self.observer,
+            connection_listener: self.connection_listener,
+            connector: self.connector,
+            checkout_deadline: self.checkout_deadline,
+            connection_descriptor: Mutex::new(None),
+            transaction_limit: self.max_open_transactions.map(|max| Arc::new(TransactionLimit::new(max, mode))),
+            single_use: self.single_use,
+            exhausted_mode: self.exhausted_mode,
+            host_selection: self.host_selection,
+            checkouts: AtomicUsize::new(0),
+        };
+        Ok(client)
+    }
+}
+
+// Settings of the pool of a host.
+#[cfg(feature = "net")]
+#[derive(Clone, Copy)]
+struct PoolSettings {
+    max_size: u32,
+    min_idle: Option<u32>,
+    connection_timeout: Duration,
+    test_on_check_out: bool,
+    // close connections when they are returned, see ClientBuilder::no_pool
+    single_use: bool,
+}
+
+// Custom connector and connection event listener passed on to the connection managers of a client.
+#[cfg(feature = "net")]
+#[derive(Clone, Copy)]
+struct ManagerHooks<'h> {
+    connector: &'h Option<Connector>,
+    listener: &'h Option<Arc<dyn ConnectionEventListener>>,
+}
+
+#[cfg(feature = "net")]
+fn build_pool(host: &Host, health: Arc<HostHealth>, socket_options: SocketOptions, settings: PoolSettings,
+        hooks: ManagerHooks) -> r2d2::Pool<AntidoteConnectionManager> {
+    let connection_manager = match hooks.connector {
+        Some(connector) => AntidoteConnectionManager::with_connector(host.address(), connector.clone()),
+        None => AntidoteConnectionManager::new(host.address()),
+    };
+    let mut connection_manager = connection_manager
+        .with_health(health)
+        .with_socket_options(socket_options)
+        .with_single_use(settings.single_use);
+    if let Some(listener) = hooks.listener {
+        connection_manager = connection_manager.with_event_listener(listener.clone());
     }
-    let client = Client {pools};
-    Ok(client)
+    let mut builder = r2d2::Pool::builder()
+        .max_size(settings.max_size)
+        .min_idle(settings.min_idle)
+        .connection_timeout(settings.connection_timeout)
+        .test_on_check_out(settings.test_on_check_out);
+    if settings.single_use {
+        // no connection stays in the pool, so there is nothing to reap
+        builder = builder.idle_timeout(None).max_lifetime(None);
+    }
+    // build_unchecked: do not wait for the hosts, unreachable hosts are reported on first use
+    builder.build_unchecked(connection_manager)
 }
 
+// Pool of temporary connections beyond the regular pool for PoolExhaustedMode::Overflow, sharing the host's health.
+#[cfg(feature = "net")]
+fn build_overflow_pool(host: &Host, health: Arc<HostHealth>, socket_options: SocketOptions, settings: PoolSettings,
+        hooks: ManagerHooks, mode: PoolExhaustedMode) -> Option<r2d2::Pool<AntidoteConnectionManager>> {
+    match mode {
+        PoolExhaustedMode::Overflow(max) if max > 0 => Some(build_pool(host, health, socket_options, PoolSettings {
+            max_size: max,
+            min_idle: Some(0),
+            single_use: true,
+            ..settings
+        }, hooks)),
+        _ => None,
+    }
+}
+
+// Whether all connections of a pool are checked out and no new one can be opened.
+#[cfg(feature = "net")]
+fn is_exhausted(pool: &r2d2::Pool<AntidoteConnectionManager>) -> bool {
+    let state = pool.state();
+    state.idle_connections == 0 && state.connections >= pool.max_size()
+}
+
+#[cfg(all(feature = "net", feature = "metrics"))]
+fn default_observer() -> Option<Arc<dyn LatencyObserver>> {
+    Some(Arc::new(observer::MetricsObserver))
+}
+
+#[cfg(all(feature = "net", not(feature = "metrics")))]
+fn default_observer() -> Option<Arc<dyn LatencyObserver>> {
+    None
+}
+
+#[cfg(feature = "net")]
 impl Client {
-    fn get_connection(&self) -> Result<r2d2::PooledConnection<AntidoteConnectionManager>, Error> {
+    // Creates a client around pre-built pools with default settings, see ClientBuilder::build_with_pools.
+    pub fn with_existing_pools(hosts: Vec<Host>, pools: Vec<r2d2::Pool<AntidoteConnectionManager>>) -> Result<Client, AntidoteError> {
+        ClientBuilder::new().hosts(hosts).build_with_pools(pools)
+    }
+
+    // Creates a client with default settings for an Antidote server on this machine listening on the given port.
+    pub fn local(port: u16) -> Result<Client, AntidoteError> {
+        new_client(vec!(Host { port, ..Host::default() }))
+    }
+
+    // Creates a client with default settings for an Antidote server on this machine listening on the default port 8087.
+    pub fn localhost() -> Result<Client, AntidoteError> {
+        new_client(vec!(Host::default()))
+    }
+
+    fn get_connection(&self) -> Result<r2d2::PooledConnection<AntidoteConnectionManager>, AntidoteError> {
+        self.get_connection_for(false)
+    }
+
+    // Checks out a connection, trying the hosts whose role matches the intent (reads or not) first.
+    pub(crate) fn get_connection_for(&self, read_intent: bool) -> Result<r2d2::PooledConnection<AntidoteConnectionManager>, AntidoteError> {
+        let start = Instant::now();
+        let state = self.host_pools();
+        let mut failures: Vec<(String, String)> = Vec::new();
+        // skip quarantined hosts, they fail or are slow anyway
+        let mut candidates: Vec<usize> = Vec::new();
+        for i in 0..state.pools.len() {
+            if state.health[i].is_quarantined() {
+                failures.push((state.hosts[i].address(), String::from("quarantined after repeated failures")));
+            } else {
+                candidates.push(i);
+            }
+        }
+        if self.host_selection == HostSelection::RoundRobin && !candidates.is_empty() {
+            let len = candidates.len();
+            candidates.rotate_left(self.checkouts.fetch_add(1, Ordering::Relaxed) % len);
+        }
+        let preferred = if read_intent { HostRole::ReadPreferred } else { HostRole::Any };
+        // stable, keeps the round robin order within the hosts of a role
+        candidates.sort_by_key(|i| state.hosts[*i].role != preferred);
         // TODO: random ordering of pools
-        for p in self.pools.iter() {
-            let conn = p.get().unwrap();
-            return Ok(conn);
+        let mut exhausted = 0;
+        // hosts whose connections all stayed in use until the timeout
+        let mut busy = 0;
+        for (n, i) in candidates.iter().enumerate() {
+            let mut pool = &state.pools[*i];
+            if self.exhausted_mode != PoolExhaustedMode::Block && is_exhausted(pool) {
+                match &state.overflow[*i] {
+                    Some(overflow) if !is_exhausted(overflow) => pool = overflow,
+                    _ if self.exhausted_mode == PoolExhaustedMode::FailFast => {
+                        exhausted += 1;
+                        failures.push((state.hosts[*i].address(), String::from("pool exhausted")));
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            // share the remaining time among the hosts not tried yet, waiting at most the pool's connection timeout
+            let remaining = self.checkout_deadline.checked_sub(start.elapsed()).unwrap_or_default();
+            let timeout = (remaining / (candidates.len() - n) as u32).min(pool.connection_timeout());
+            match pool.get_timeout(timeout) {
+                Ok(conn) => {
+                    observer::observe(&self.observer, Operation::Checkout, start, true, None);
+                    return Ok(conn);
+                }
+                Err(e) => {
+                    if is_exhausted(pool) {
+                        busy += 1;
+                    }
+                    failures.push((state.hosts[*i].address(), e.to_string()))
+                }
+            }
         }
-        Err(Error::new(ErrorKind::Other, format!("All connections dead")))
+        observer::observe(&self.observer, Operation::Checkout, start, false, None);
+        if exhausted > 0 && exhausted == candidates.len() {
+            return Err(AntidoteError::PoolExhausted);
+        }
+        if busy > 0 && busy + exhausted == candidates.len() {
+            return Err(AntidoteError::PoolTimeout(start.elapsed()));
+        }
+        Err(AntidoteError::NoHealthyHosts(failures))
     }
 
-    pub fn start_transaction(&self) -> Result<InteractiveTransaction, Error> {
-        let mut conn = self.get_connection()?;
-        let read_write: u32 = 0;
-        let blue: u32 = 0;
-        let mut apb_txn_properties = antidote_pb::ApbTxnProperties::new();
-        apb_txn_properties.set_read_write(read_write);
-        apb_txn_properties.set_red_blue(blue);
-        let mut apb_txn = antidote_pb::ApbStartTransaction::new();
-        apb_txn.set_properties(apb_txn_properties);
-
-        apb_txn.encode(&mut *conn)?;
-        let apb_txn_resp = coder::decode_start_transaction_resp(&mut *conn)?;
-
-        let txn_desc = apb_txn_resp.get_transaction_descriptor();
-        let tx = InteractiveTransaction {
-            conn,
-            tx_id: txn_desc.to_vec(),
-            committed: false,
+    fn host_pools(&self) -> Arc<HostPools> {
+        self.host_pools.read().unwrap().clone()
+    }
+
+    // The Antidote servers this client is connected to.
+    pub fn hosts(&self) -> Vec<Host> {
+        self.host_pools().hosts.clone()
+    }
+
+    pub fn host_count(&self) -> usize {
+        self.host_pools().hosts.len()
+    }
+
+    // Number of connections currently open to all hosts, idle or in use.
+    pub fn open_connections(&self) -> u32 {
+        self.host_pools().pools.iter().map(|p| p.state().connections).sum()
+    }
+
+    // Number of interactive transactions currently open, counted only if max_open_transactions is set.
+    pub fn open_transactions(&self) -> usize {
+        self.transaction_limit.as_ref().map_or(0, |l| l.open())
+    }
+
+    // Number of connections currently checked out of the pools (e.g. by running transactions).
+    pub fn in_use_connections(&self) -> u32 {
+        self.host_pools().pools.iter().map(|p| {
+            let state = p.state();
+            state.connections - state.idle_connections
+        }).sum()
+    }
+
+    // Replaces a host (identified by its name or 'name:port' address) by a new one with a pool of the same settings.
+    // Transactions running on connections to the old host continue on them until they finish,
+    // the old pool is closed once its last connection is returned.
+    // Fails with InvalidInput if a name matches several hosts (on different ports, use the address then)
+    // or if the new host is already configured as another host.
+    // Checkouts running concurrently use either the old or the new host.
+    pub fn replace_host(&self, old: &str, new: Host) -> Result<(), AntidoteError> {
+        validate_host(&new)?;
+        let mut current = self.host_pools.write().unwrap();
+        let hosts = &current.hosts;
+        let matching: Vec<usize> = (0..hosts.len()).filter(|i| hosts[*i].address() == old).collect();
+        let matching = match matching.is_empty() {
+            true => (0..hosts.len()).filter(|i| hosts[*i].name == old).collect(),
+            false => matching,
+        };
+        let index = match matching.as_slice() {
+            [i] => *i,
+            [] => return Err(Error::new(ErrorKind::NotFound, format!("Host {} is not configured", old)).into()),
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("Host name {} is configured {} times, give the address 'name:port'", old, matching.len())).into()),
         };
-        return Ok(tx)
+        if hosts.iter().enumerate().any(|(i, h)| i != index && h.address() == new.address()) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Host {} is configured more than once", new.address())).into())
+        }
+        let mut next = HostPools::clone(&current);
+        self.rebuild_pool(&mut next, index, new);
+        *current = Arc::new(next);
+        Ok(())
+    }
+
+    // Replaces the pools of all hosts by new ones of the same settings and resets the health of the hosts,
+    // e.g. after a restart of the cluster left the pools with stale connections that would fail one by one.
+    // The new pools connect lazily like on build. Transactions running on connections of the old pools
+    // are unaffected and continue on them until they finish, the old pools are closed once their last connection
+    // is returned. Pools passed to `with_existing_pools` are replaced by TCP pools, as with `replace_host`.
+    pub fn reconnect_all(&self) {
+        let mut current = self.host_pools.write().unwrap();
+        let mut next = HostPools::clone(&current);
+        for index in 0..next.hosts.len() {
+            let host = next.hosts[index].clone();
+            self.rebuild_pool(&mut next, index, host);
+        }
+        *current = Arc::new(next);
+    }
+
+    fn rebuild_pool(&self, state: &mut HostPools, index: usize, new: Host) {
+        let old_pool = &state.pools[index];
+        let old_health = &state.health[index];
+        let health = Arc::new(HostHealth::new(old_health.failure_threshold(), old_health.quarantine_period()));
+        let settings = PoolSettings {
+            max_size: old_pool.max_size(),
+            min_idle: old_pool.min_idle(),
+            connection_timeout: old_pool.connection_timeout(),
+            test_on_check_out: old_pool.test_on_check_out(),
+            single_use: self.single_use,
+        };
+        let hooks = ManagerHooks { connector: &self.connector, listener: &self.connection_listener };
+        let pool = build_pool(&new, health.clone(), self.socket_options, settings, hooks);
+        // keeps the size of the old overflow pool, which may have been lowered to fit the connection budget
+        if let Some(overflow) = &state.overflow[index] {
+            let mode = PoolExhaustedMode::Overflow(overflow.max_size());
+            state.overflow[index] = build_overflow_pool(&new, health.clone(), self.socket_options, settings, hooks, mode);
+        }
+        state.pools[index] = pool;
+        state.health[index] = health;
+        state.hosts[index] = new;
+        *self.connection_descriptor.lock().unwrap() = None;
     }
 
-    pub fn create_static_transaction<'clt>(&'clt mut self) -> Result<StaticTransaction<'clt>, Error> {
+    // Starts an interactive read-write transaction with default properties.
+    pub fn start_transaction(&self) -> Result<InteractiveTransaction, AntidoteError> {
+        self.transaction_builder().start()
+    }
+
+    // Starts an interactive read-write transaction tagged with a trace id (client-side only, not sent to Antidote)
+    // that is passed to the latency observer with each of its operations.
+    pub fn transaction_with_trace_id(&self, trace_id: &str) -> Result<InteractiveTransaction, AntidoteError> {
+        self.transaction_builder().trace_id(trace_id).start()
+    }
+
+    // Configures the properties of an interactive transaction before starting it.
+    pub fn transaction_builder(&self) -> TransactionBuilder<'_> {
+        TransactionBuilder::new(self)
+    }
+
+    // Commits the transactions one after the other and returns their commit times, in the order of `txns`.
+    // If a commit fails, the transactions after it are aborted and AntidoteError::PartialCommit tells which one failed
+    // and the commit times of the ones committed before it.
+    // This is best-effort coordination on the client, not an atomic commit: the transactions stay independent on the
+    // server, those committed before the failure remain committed (and may already be visible to others).
+    pub fn commit_all(&self, txns: Vec<InteractiveTransaction>) -> Result<Vec<Vec<u8>>, AntidoteError> {
+        let mut commit_times = Vec::with_capacity(txns.len());
+        let mut txns = txns.into_iter();
+        while let Some(mut tx) = txns.next() {
+            match tx.commit_detailed() {
+                Ok(info) => commit_times.push(info.commit_time),
+                Err(source) => {
+                    for mut rest in txns {
+                        // a failed abort leaves the transaction to the server's timeout, the failure reported is the commit's
+                        let _ = rest.abort();
+                    }
+                    return Err(AntidoteError::PartialCommit { commit_times, source: Box::new(source) });
+                }
+            }
+        }
+        Ok(commit_times)
+    }
+
+    // Reads the objects at a single snapshot and returns their values in the order they were added, converted according
+    // to the types they were added with. Uses a static read: a single request Antidote runs as one transaction,
+    // so all objects are read at the same snapshot without the round trips to start and commit an interactive transaction.
+    pub fn consistent_read(&self, objects: &MultiRead) -> Result<Vec<CRDTValue>, AntidoteError> {
+        let mut tx = self.create_static_transaction()?;
+        objects.read_values(&mut tx)
+    }
+
+    pub fn create_static_transaction<'clt>(&'clt self) -> Result<StaticTransaction<'clt>, AntidoteError> {
         let static_transaction = StaticTransaction {
             client: self,
         };
         Ok(static_transaction)
     }
 
-    pub fn create_dc(&mut self, node_names: Vec<String>) -> Result<(), Error> {
+    // Creates a DC out of the given Antidote nodes.
+    // Node names are Erlang node names of the form 'antidote@hostname' or 'antidote@ip'.
+    pub fn create_dc(&self, node_names: Vec<String>) -> Result<(), AntidoteError> {
+        for name in node_names.iter() {
+            validate_node_name(name)?;
+        }
         let mut conn = self.get_connection()?;
         let mut create_dc = antidote_pb::ApbCreateDC::new();
         create_dc.set_nodes(protobuf::RepeatedField::from_vec(node_names));
-        create_dc.encode(&mut *conn)?;
-        let resp = coder::decode_apb_create_dc_resp(&mut *conn)?;
+        let resp = conn.exchange(|c| { create_dc.encode(c)?; coder::decode_apb_create_dc_resp(c) })?;
         if !resp.get_success() {
-            return Err(Error::new(ErrorKind::Other, format!("Could not create DC, error code {}", resp.get_errorcode())))
+            return Err(AntidoteError::OperationFailed { code: ErrorCode::from(resp.get_errorcode()), message: None })
         }
         Ok(())
     }
 
-    pub fn get_connection_descriptor(&mut self) -> Result<Vec<u8>, Error> {
+    // Returns the connection descriptor of the DC, fetched once and cached since it is stable for the lifetime of a node.
+    pub fn get_connection_descriptor(&self) -> Result<Vec<u8>, AntidoteError> {
+        if let Some(descriptor) = self.connection_descriptor.lock().unwrap().as_ref() {
+            return Ok(descriptor.clone());
+        }
+        self.refresh_connection_descriptor()
+    }
+
+    // Fetches the connection descriptor again, replacing the cached one.
+    pub fn refresh_connection_descriptor(&self) -> Result<Vec<u8>, AntidoteError> {
         let mut conn = self.get_connection()?;
         let get_cd = antidote_pb::ApbGetConnectionDescriptor::new();
-        get_cd.encode(&mut *conn)?;
-        let mut resp = coder::decode_apb_get_connection_descriptor_resp(&mut *conn)?;
+        let mut resp = conn.exchange(|c| { get_cd.encode(c)?; coder::decode_apb_get_connection_descriptor_resp(c) })?;
         if !resp.get_success() {
-            return Err(Error::new(ErrorKind::Other, format!("Could not get connection descriptor, error code {}", resp.get_errorcode())))
+            return Err(AntidoteError::OperationFailed { code: ErrorCode::from(resp.get_errorcode()), message: None })
         }
         let descriptor = resp.take_d();
+        *self.connection_descriptor.lock().unwrap() = Some(descriptor.clone());
         Ok(descriptor)
     }
 
-    pub fn connect_to_dcs(&mut self, descriptors: Vec<Vec<u8>>) -> Result<(), Error> {
+    // The nodes of the DC as Antidote sees them, read from a freshly fetched connection descriptor (one entry per node,
+    // in Antidote's order). Compare with hosts() to spot nodes missing from the client configuration or vice versa.
+    // The descriptor format is internal to Antidote, an unknown format fails with InvalidData.
+    pub fn node_status(&self) -> Result<Vec<NodeStatus>, AntidoteError> {
+        Ok(descriptor::parse_nodes(&self.refresh_connection_descriptor()?)?)
+    }
+
+    pub fn connect_to_dcs(&self, descriptors: Vec<Vec<u8>>) -> Result<(), AntidoteError> {
         let mut conn = self.get_connection()?;
         let mut connect_to_dcs = antidote_pb::ApbConnectToDCs::new();
         connect_to_dcs.set_descriptors(protobuf::RepeatedField::from_vec(descriptors));
-        connect_to_dcs.encode(&mut *conn)?;
-        let resp = coder::decode_apb_connect_to_dcs_resp(&mut *conn)?;
+        let resp = conn.exchange(|c| { connect_to_dcs.encode(c)?; coder::decode_apb_connect_to_dcs_resp(c) })?;
         if !resp.get_success() {
-            return Err(Error::new(ErrorKind::Other, format!("Could not connect DCs, error code {}", resp.get_errorcode())))
+            return Err(AntidoteError::OperationFailed { code: ErrorCode::from(resp.get_errorcode()), message: None })
         }
         Ok(())
     }
 }
+
+// A client that can be cloned cheaply and shared across threads, e.g. one clone per worker thread.
+// All operations of Client take &self and are available through Deref.
+#[cfg(feature = "net")]
+#[derive(Clone)]
+pub struct SharedClient {
+    client: Arc<Client>,
+}
+
+#[cfg(feature = "net")]
+impl SharedClient {
+    pub fn new(client: Client) -> SharedClient {
+        SharedClient {
+            client: Arc::new(client),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl From<Client> for SharedClient {
+    fn from(client: Client) -> SharedClient {
+        SharedClient::new(client)
+    }
+}
+
+#[cfg(feature = "net")]
+impl std::ops::Deref for SharedClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+// Checks that the port of a host is a valid TCP port, so a typo does not end up as a cryptic connect error.
+#[cfg(feature = "net")]
+fn validate_host(host: &Host) -> Result<(), AntidoteError> {
+    if host.port == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid port {} of host {}, expected 1..65535", host.port, host.name)).into())
+    }
+    Ok(())
+}
+
+// Checks that a node name has the form 'name@host', so a typo does not end up as an opaque server-side error code.
+#[cfg(feature = "net")]
+fn validate_node_name(name: &str) -> Result<(), AntidoteError> {
+    let parts: Vec<&str> = name.split('@').collect();
+    let well_formed = parts.len() == 2
+        && !parts[0].is_empty()
+        && !parts[1].is_empty()
+        && !name.chars().any(|c| c.is_whitespace());
+    if !well_formed {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid node name '{}', expected the form 'name@host' (e.g. 'antidote@dc1n1')", name)).into())
+    }
+    Ok(())
+}
@@ -1,206 +1,323 @@
+// Protocol layer: framing and (de)coding of Antidote's protocol-buffer messages.
+// Generic over Read/Write, so the same framing code serves every transport (pooled TCP connection, buffers, ...).
 use crate::antidote_pb::*;
+use crate::error::{AntidoteError, DesyncReason, ErrorCode};
 use byteorder::{ByteOrder, BigEndian};
-use protobuf::{Message};
+use protobuf::{CodedOutputStream, Message, ProtobufError};
+use protobuf::reflect::{ReflectFieldRef, ReflectValueRef};
 use std::io::{Read, Write, Error, ErrorKind};
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-fn read_msg_raw(reader: &mut dyn Read) -> Result<Vec<u8>, Error> {
+fn read_msg_raw<R: Read + ?Sized>(reader: &mut R) -> Result<Vec<u8>, Error> {
     let mut size_b : [u8; 4] = [0; 4];
     // read the size of the message
     let mut count : usize = 0;
     while count < 4 {
         let n = reader.read(&mut size_b[count..])?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Connection closed by Antidote"));
+        }
         count += usize::from(n);
     }
-    let size_i : usize = BigEndian::read_u32(&size_b) as usize;
+    let size_i = frame_size(&size_b)?;
     let mut data : Vec<u8> = Vec::new();
     data.resize(size_i, 0);
 
     count = 0;
     while count < size_i {
         let n = reader.read(&mut data[count..])?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Connection closed by Antidote"));
+        }
         count += usize::from(n);
     }     
     Ok(data)
 }
 
-fn encode_msg(message: &dyn Message, msg_code: u8, writer: &mut dyn Write) -> Result<(), Error> {
-    let mut msg : Vec<u8> = message.write_to_bytes().unwrap();
-    let msg_size: usize = msg.len()+1;
+// Size of the message announced in a frame header.
+fn frame_size(size_b: &[u8; 4]) -> Result<usize, Error> {
+    // a status line instead of a message size: the host is an HTTP endpoint, not Antidote's protocol-buffer interface
+    if size_b == b"HTTP" {
+        return Err(Error::new(ErrorKind::InvalidData, "Received an HTTP response, this looks like an HTTP endpoint; did you mean Antidote's protocol-buffer port (usually 8087)?"));
+    }
+    // every message of Antidote starts with its message code, an empty frame means the stream is corrupt
+    match BigEndian::read_u32(size_b) as usize {
+        0 => Err(AntidoteError::StreamDesync(DesyncReason::EmptyFrame).into()),
+        size => Ok(size),
+    }
+}
+
+/// Reads a frame from an async stream, returning the message code followed by the encoded message
+/// (like the `decode_*` functions expect it, e.g. merge `&data[1..]` into the response matching `data[0]`).
+#[cfg(feature = "async")]
+pub async fn read_msg_raw_async<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut size_b : [u8; 4] = [0; 4];
+    reader.read_exact(&mut size_b).await.map_err(connection_closed)?;
+    let mut data = vec![0; frame_size(&size_b)?];
+    reader.read_exact(&mut data).await.map_err(connection_closed)?;
+    Ok(data)
+}
+
+#[cfg(feature = "async")]
+fn connection_closed(e: Error) -> Error {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => Error::new(ErrorKind::UnexpectedEof, "Connection closed by Antidote"),
+        _ => e,
+    }
+}
+
+/// Writes a message with the given message code (e.g. 119 to start a transaction, see the `encode` methods)
+/// as a frame to an async stream. The frame is encoded in memory first and written at once.
+#[cfg(feature = "async")]
+pub async fn encode_msg_async<W: AsyncWrite + Unpin + ?Sized>(message: &dyn Message, msg_code: u8, writer: &mut W) -> Result<(), Error> {
+    let mut buf = Vec::new();
+    encode_msg(message, msg_code, &mut buf)?;
+    writer.write_all(&buf).await?;
+    writer.flush().await
+}
+
+// Largest message a frame can carry: the size in its 4 byte header counts the message code as well.
+const MAX_MESSAGE_SIZE: u64 = u32::MAX as u64 - 1;
+
+fn encode_msg<W: Write + ?Sized>(message: &dyn Message, msg_code: u8, mut writer: &mut W) -> Result<(), Error> {
+    let encode_error = |e| protobuf_error(e, message, msg_code);
+    message.check_initialized().map_err(encode_error)?;
+    // protobuf sums up message sizes as u32 without overflow checks (panicking in debug builds, wrapping in release),
+    // so compute_size only runs on messages whose size is known to fit the frame header
+    let too_large = || Error::new(ErrorKind::InvalidInput, format!("Cannot encode {} (message code {}): larger than the {} bytes a frame can hold",
+        message.descriptor().name(), msg_code, MAX_MESSAGE_SIZE));
+    if size_bound(message, MAX_MESSAGE_SIZE).is_none() {
+        return Err(too_large());
+    }
+    // frame size: message plus code; compute_size also caches the sizes of the nested messages for writing them
+    let msg_size = message.compute_size().checked_add(1).ok_or_else(too_large)?;
     let mut buf : [u8; 5] = [0; 5];
-    BigEndian::write_u32_into(&[msg_size as u32], &mut buf[0..4]);
+    BigEndian::write_u32_into(&[msg_size], &mut buf[0..4]);
     buf[4] = msg_code;
-    writer.write(&mut buf)?;
-    writer.write(&mut msg)?;
+    // stream header and message directly into the writer instead of serializing into an intermediate Vec first
+    let mut os = CodedOutputStream::new(&mut writer);
+    os.write_raw_bytes(&buf).map_err(encode_error)?;
+    message.write_to_with_cached_sizes(&mut os).map_err(encode_error)?;
+    os.flush().map_err(encode_error)?;
     Ok(())
 }
 
+// Upper bound of the encoded size of a message, None if it exceeds the limit. Every field value is counted with
+// the largest tag and length prefix it can have, the sizes are added up with overflow checks.
+fn size_bound(message: &dyn Message, limit: u64) -> Option<u64> {
+    // tag or length prefix of a value (a varint of at most 32 bits), scalars take at most a 64 bit varint
+    const PREFIX: u64 = 5;
+    const SCALAR: u64 = 10;
+    fn value_bound(value: ReflectValueRef, limit: u64) -> Option<u64> {
+        let size = match value {
+            ReflectValueRef::String(s) => (s.len() as u64).checked_add(PREFIX)?,
+            ReflectValueRef::Bytes(b) => (b.len() as u64).checked_add(PREFIX)?,
+            ReflectValueRef::Message(m) => size_bound(m, limit)?.checked_add(PREFIX)?,
+            _ => SCALAR,
+        };
+        PREFIX.checked_add(size)
+    }
+    let mut size: u64 = 0;
+    let mut add = |bound: Option<u64>| -> Option<()> {
+        size = size.checked_add(bound?).filter(|s| *s <= limit)?;
+        Some(())
+    };
+    for field in message.descriptor().fields() {
+        match field.get_reflect(message) {
+            ReflectFieldRef::Optional(Some(value)) => add(value_bound(value, limit))?,
+            ReflectFieldRef::Optional(None) => {}
+            ReflectFieldRef::Repeated(values) => {
+                // the tag and length of a packed field
+                add(Some(2 * PREFIX))?;
+                for i in 0..values.len() {
+                    add(value_bound(values.get(i).as_ref(), limit))?;
+                }
+            }
+            ReflectFieldRef::Map(entries) => {
+                for (key, value) in entries.reflect_iter() {
+                    add(Some(2 * PREFIX))?;
+                    add(value_bound(key.as_ref(), limit))?;
+                    add(value_bound(value.as_ref(), limit))?;
+                }
+            }
+        }
+    }
+    for (_, values) in message.get_unknown_fields().iter() {
+        add(Some((values.fixed32.len() + values.fixed64.len() + values.varint.len()) as u64 * (PREFIX + SCALAR)))?;
+        for bytes in values.length_delimited.iter() {
+            add((bytes.len() as u64).checked_add(2 * PREFIX))?;
+        }
+    }
+    Some(size)
+}
+
+// I/O errors are passed on as they are, serialization errors name the message that could not be encoded.
+fn protobuf_error(e: ProtobufError, message: &dyn Message, msg_code: u8) -> Error {
+    match e {
+        ProtobufError::IoError(e) => e,
+        e => Error::new(ErrorKind::InvalidData, format!("Cannot encode {} (message code {}): {}", message.descriptor().name(), msg_code, e)),
+    }
+}
+
 impl ApbReadObjects {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 116, writer);
     }
 }
 impl ApbUpdateObjects {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 118, writer);
     }
 }
 impl ApbStartTransaction {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 119, writer);
     }
 }
 impl ApbAbortTransaction {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 120, writer);
     }
 }
 impl ApbCommitTransaction {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 121, writer);
     }
 }
 impl ApbStaticUpdateObjects {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 122, writer);
     }
 }
 impl ApbStaticReadObjects {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 123, writer);
     }
 }
 impl ApbCreateDC {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 129, writer);
     }
 }
 impl ApbConnectToDCs {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 131, writer);
     }
 }
 impl ApbGetConnectionDescriptor {
-    pub fn encode(&self, writer : &mut dyn Write) -> Result<(), Error> {
+    pub fn encode<W: Write + ?Sized>(&self, writer : &mut W) -> Result<(), Error> {
         return encode_msg(self, 133, writer);
     }
 }
 
-pub fn decode_operation_resp(reader: &mut dyn Read) -> Result<ApbOperationResp, Error> {
+// Parses the message of a frame (after its message code). A malformed message fails with InvalidData; the frame
+// was read completely, so the stream stays in sync.
+fn decode_msg<M: Message>(data: &[u8]) -> Result<M, Error> {
+    let mut resp = M::new();
+    resp.merge_from_bytes(&data[1..]).map_err(|e| Error::new(ErrorKind::InvalidData,
+        format!("Cannot decode {} (message code {}): {}", resp.descriptor().name(), data[0], e)))?;
+    Ok(resp)
+}
+
+// Error for a response with another message code than the one expected for the request.
+// Antidote answers requests it failed to handle with an error response (code 0), any other code means
+// the response belongs to another request: the stream is out of sync and the connection must not be reused.
+fn unexpected_response(data: &[u8], expected: u8) -> Error {
+    if data[0] == 0 {
+        let mut resp = ApbErrorResp::new();
+        if resp.merge_from_bytes(&data[1..]).is_ok() {
+            let message = String::from_utf8_lossy(resp.get_errmsg()).into_owned();
+            return AntidoteError::OperationFailed { code: ErrorCode::from(resp.get_errcode()), message: Some(message) }.into();
+        }
+    }
+    AntidoteError::UnexpectedMessageCode { expected, got: data[0] }.into()
+}
+
+pub fn decode_operation_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbOperationResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        111 => {
-            let mut resp = ApbOperationResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        111 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 111.", data[0])))
+            Err(unexpected_response(&data, 111))
         }
     }
 }
 
-pub fn decode_start_transaction_resp(reader: &mut dyn Read) -> Result<ApbStartTransactionResp, Error> {
+pub fn decode_start_transaction_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbStartTransactionResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        124 => {
-            let mut resp = ApbStartTransactionResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        124 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 124.", data[0])))
+            Err(unexpected_response(&data, 124))
         }
     }
 }
 
-pub fn decode_read_objects_resp(reader: &mut dyn Read) -> Result<ApbReadObjectsResp, Error> {
+pub fn decode_read_objects_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbReadObjectsResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        126 => {
-            let mut resp = ApbReadObjectsResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        126 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 126.", data[0])))
+            Err(unexpected_response(&data, 126))
         }
     }
 }
 
-pub fn decode_commit_resp(reader: &mut dyn Read) -> Result<ApbCommitResp, Error> {
+pub fn decode_commit_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbCommitResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        127 => {
-            let mut resp = ApbCommitResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        127 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 127.", data[0])))
+            Err(unexpected_response(&data, 127))
         }
     }
 }
 
-pub fn decode_static_read_objects_resp(reader: &mut dyn Read) -> Result<ApbStaticReadObjectsResp, Error> {
+pub fn decode_static_read_objects_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbStaticReadObjectsResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        128 => {
-            let mut resp = ApbStaticReadObjectsResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        128 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 128.", data[0])))
+            Err(unexpected_response(&data, 128))
         }
     }
 }
 
-pub fn decode_apb_create_dc_resp(reader: &mut dyn Read) -> Result<ApbCreateDCResp, Error> {
+pub fn decode_apb_create_dc_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbCreateDCResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        130 => {
-            let mut resp = ApbCreateDCResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        130 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 130.", data[0])))
+            Err(unexpected_response(&data, 130))
         }
     }
 }
 
-pub fn decode_apb_connect_to_dcs_resp(reader: &mut dyn Read) -> Result<ApbConnectToDCsResp, Error> {
+pub fn decode_apb_connect_to_dcs_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbConnectToDCsResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        132 => {
-            let mut resp = ApbConnectToDCsResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        132 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 132.", data[0])))
+            Err(unexpected_response(&data, 132))
         }
     }
 }
 
-pub fn decode_apb_get_connection_descriptor_resp(reader: &mut dyn Read) -> Result<ApbGetConnectionDescriptorResp, Error> {
+pub fn decode_apb_get_connection_descriptor_resp<R: Read + ?Sized>(reader: &mut R) -> Result<ApbGetConnectionDescriptorResp, Error> {
     let data :Vec<u8> = read_msg_raw(reader)?;
     match data[0] {
         // transaction response
-        134 => {
-            let mut resp = ApbGetConnectionDescriptorResp::new();
-            resp.merge_from_bytes(&data[1..]).unwrap(); // Unmarshal from go?
-            return Ok(resp);
-        }
+        134 => decode_msg(&data),
         _ => {
-            Err(Error::new(ErrorKind::Other, format!("Invalid message code: {}. Expected 134.", data[0])))
+            Err(unexpected_response(&data, 134))
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,261 @@
+// Reads the descriptors Antidote hands out as opaque bytes, both are Erlang terms in the external term format
+// (term_to_binary). The formats are internal to Antidote and may change between versions.
+// - connection descriptor: {descriptor, DCID, PartitionNum, Publishers, LogReaders}, where Publishers and
+//   LogReaders hold one list of {IP, Port} addresses per node of the DC.
+// - transaction descriptor: {tx_id, LocalStartTime, ServerPid}, the start time in microseconds on the
+//   coordinating node and the process coordinating the transaction.
+use byteorder::{ByteOrder, BigEndian};
+use std::io::{Error, ErrorKind};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+
+// A node of the DC as seen by Antidote, listed in the DC's connection descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeStatus {
+    // Addresses ('ip:port') the node publishes its transactions on to other DCs.
+    pub publishers: Vec<String>,
+    // Addresses ('ip:port') other DCs query for missing log entries of the node.
+    pub log_readers: Vec<String>,
+}
+
+// Fields of a transaction descriptor, see `InteractiveTransaction::descriptor`.
+// The descriptor does not contain the snapshot clock of the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionDescriptor {
+    // Start time of the transaction in microseconds since the epoch, as taken on the coordinating node.
+    pub local_start_time: u64,
+    // Node of the process coordinating the transaction, None for a locally registered process.
+    pub server_node: Option<String>,
+    // Process coordinating the transaction as printed in the node's logs ('<0.ID.Serial>'),
+    // or the registered name.
+    pub server_process: String,
+}
+
+// The subset of Erlang terms a descriptor is made of.
+#[derive(Debug)]
+enum Term {
+    Atom(String),
+    Int(i64),
+    Tuple(Vec<Term>),
+    List(Vec<Term>),
+    Bytes(Vec<u8>),
+    Pid { node: String, id: usize, serial: usize },
+}
+
+pub(crate) fn parse_nodes(descriptor: &[u8]) -> Result<Vec<NodeStatus>, Error> {
+    let term = match descriptor.split_first() {
+        Some((131, rest)) => Decoder { data: rest, pos: 0, depth: 0, kind: CONNECTION }.term()?,
+        _ => return Err(unrecognized("not an Erlang term")),
+    };
+    let fields = match term {
+        Term::Tuple(fields) if fields.len() == 5 && matches!(&fields[0], Term::Atom(a) if a == "descriptor") => fields,
+        _ => return Err(unrecognized("not a descriptor record")),
+    };
+    let publishers = addresses_per_node(&fields[3])?;
+    let log_readers = addresses_per_node(&fields[4])?;
+    if publishers.len() != log_readers.len() {
+        return Err(unrecognized("publishers and log readers of a different number of nodes"));
+    }
+    Ok(publishers.into_iter().zip(log_readers).map(|(publishers, log_readers)| NodeStatus { publishers, log_readers }).collect())
+}
+
+pub(crate) fn parse_transaction(descriptor: &[u8]) -> Result<TransactionDescriptor, Error> {
+    let term = match descriptor.split_first() {
+        Some((131, rest)) => Decoder { data: rest, pos: 0, depth: 0, kind: TRANSACTION }.term()?,
+        _ => return Err(unrecognized_transaction("not an Erlang term")),
+    };
+    let fields = match term {
+        Term::Tuple(fields) if fields.len() == 3 && matches!(&fields[0], Term::Atom(a) if a == "tx_id") => fields,
+        _ => return Err(unrecognized_transaction("not a tx_id record")),
+    };
+    let local_start_time = match fields[1] {
+        Term::Int(t) if t >= 0 => t as u64,
+        _ => return Err(unrecognized_transaction("start time is not a clock time")),
+    };
+    let (server_node, server_process) = match &fields[2] {
+        Term::Pid { node, id, serial } => (Some(node.clone()), format!("<0.{}.{}>", id, serial)),
+        Term::Atom(name) => (None, name.clone()),
+        _ => return Err(unrecognized_transaction("server is neither a pid nor a name")),
+    };
+    Ok(TransactionDescriptor { local_start_time, server_node, server_process })
+}
+
+fn addresses_per_node(term: &Term) -> Result<Vec<Vec<String>>, Error> {
+    match term {
+        Term::List(nodes) => nodes.iter().map(|node| match node {
+            Term::List(addresses) => addresses.iter().map(address).collect(),
+            _ => Err(unrecognized("node entry is not a list of addresses")),
+        }).collect(),
+        _ => Err(unrecognized("address lists missing")),
+    }
+}
+
+fn address(term: &Term) -> Result<String, Error> {
+    let (ip, port) = match term {
+        Term::Tuple(t) if t.len() == 2 => (&t[0], &t[1]),
+        _ => return Err(unrecognized("address is not an {IP, Port} tuple")),
+    };
+    let port = match port {
+        Term::Int(p) => *p,
+        _ => return Err(unrecognized("port is not an integer")),
+    };
+    let ip = match ip {
+        Term::Tuple(parts) => {
+            let parts: Vec<i64> = parts.iter().map(|p| match p {
+                Term::Int(i) => Ok(*i),
+                _ => Err(unrecognized("IP part is not an integer")),
+            }).collect::<Result<_, _>>()?;
+            match parts.len() {
+                4 => Ipv4Addr::new(parts[0] as u8, parts[1] as u8, parts[2] as u8, parts[3] as u8).to_string(),
+                8 => format!("[{}]", Ipv6Addr::new(parts[0] as u16, parts[1] as u16, parts[2] as u16, parts[3] as u16,
+                    parts[4] as u16, parts[5] as u16, parts[6] as u16, parts[7] as u16)),
+                _ => return Err(unrecognized("IP tuple of unknown size")),
+            }
+        }
+        // host names are strings, i.e. lists of characters
+        Term::Bytes(b) => String::from_utf8_lossy(b).into_owned(),
+        Term::List(chars) => chars.iter().map(|c| match c {
+            Term::Int(c) => Ok(*c as u8 as char),
+            _ => Err(unrecognized("host name is not a string")),
+        }).collect::<Result<String, _>>()?,
+        Term::Atom(a) => a.clone(),
+        _ => return Err(unrecognized("IP of unknown type")),
+    };
+    Ok(format!("{}:{}", ip, port))
+}
+
+const CONNECTION: &str = "connection";
+const TRANSACTION: &str = "transaction";
+// Nesting of tuples and lists a descriptor may have, a few levels in practice; bounds the recursion of the decoder.
+const MAX_DEPTH: usize = 32;
+
+fn unrecognized_kind(kind: &str, reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Unrecognized {} descriptor: {}", kind, reason))
+}
+
+fn unrecognized(reason: &str) -> Error {
+    unrecognized_kind(CONNECTION, reason)
+}
+
+fn unrecognized_transaction(reason: &str) -> Error {
+    unrecognized_kind(TRANSACTION, reason)
+}
+
+struct Decoder<'d> {
+    data: &'d [u8],
+    pos: usize,
+    // terms being decoded that contain the current one
+    depth: usize,
+    // descriptor being decoded, for the error messages
+    kind: &'static str,
+}
+
+impl<'d> Decoder<'d> {
+    fn take(&mut self, n: usize) -> Result<&'d [u8], Error> {
+        if self.data.len() - self.pos < n {
+            return Err(unrecognized_kind(self.kind, "truncated"));
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<usize, Error> {
+        Ok(self.take(1)?[0] as usize)
+    }
+
+    fn u16(&mut self) -> Result<usize, Error> {
+        Ok(BigEndian::read_u16(self.take(2)?) as usize)
+    }
+
+    fn u32(&mut self) -> Result<usize, Error> {
+        Ok(BigEndian::read_u32(self.take(4)?) as usize)
+    }
+
+    fn term(&mut self) -> Result<Term, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(unrecognized_kind(self.kind, "nested too deeply"));
+        }
+        self.depth += 1;
+        let term = self.term_at_depth();
+        self.depth -= 1;
+        term
+    }
+
+    fn term_at_depth(&mut self) -> Result<Term, Error> {
+        let tag = self.u8()?;
+        let term = match tag {
+            // SMALL_INTEGER_EXT, INTEGER_EXT
+            97 => Term::Int(self.u8()? as i64),
+            98 => Term::Int(BigEndian::read_i32(self.take(4)?) as i64),
+            // SMALL_BIG_EXT, e.g. timestamps
+            110 => {
+                let n = self.u8()?;
+                let sign = self.u8()?;
+                let digits = self.take(n)?;
+                if n > 7 {
+                    return Err(unrecognized_kind(self.kind, "integer too large"));
+                }
+                let value = digits.iter().rev().fold(0i64, |v, d| (v << 8) | *d as i64);
+                Term::Int(if sign == 0 { value } else { -value })
+            }
+            // ATOM_EXT, SMALL_ATOM_EXT, ATOM_UTF8_EXT, SMALL_ATOM_UTF8_EXT
+            100 | 118 => {
+                let n = self.u16()?;
+                Term::Atom(String::from_utf8_lossy(self.take(n)?).into_owned())
+            }
+            115 | 119 => {
+                let n = self.u8()?;
+                Term::Atom(String::from_utf8_lossy(self.take(n)?).into_owned())
+            }
+            // SMALL_TUPLE_EXT, LARGE_TUPLE_EXT
+            104 => {
+                let n = self.u8()?;
+                Term::Tuple(self.terms(n)?)
+            }
+            105 => {
+                let n = self.u32()?;
+                Term::Tuple(self.terms(n)?)
+            }
+            // NIL_EXT, STRING_EXT, LIST_EXT (the tail of a proper list is NIL_EXT)
+            106 => Term::List(Vec::new()),
+            107 => {
+                let n = self.u16()?;
+                Term::Bytes(self.take(n)?.to_vec())
+            }
+            108 => {
+                let n = self.u32()?;
+                let elements = self.terms(n)?;
+                self.term()?;
+                Term::List(elements)
+            }
+            // BINARY_EXT
+            109 => {
+                let n = self.u32()?;
+                Term::Bytes(self.take(n)?.to_vec())
+            }
+            // PID_EXT, NEW_PID_EXT (differ in the size of the creation)
+            103 | 88 => {
+                let node = match self.term()? {
+                    Term::Atom(node) => node,
+                    _ => return Err(unrecognized_kind(self.kind, "pid without node")),
+                };
+                let id = self.u32()?;
+                let serial = self.u32()?;
+                self.take(if tag == 103 { 1 } else { 4 })?;
+                Term::Pid { node, id, serial }
+            }
+            tag => return Err(unrecognized_kind(self.kind, &format!("unsupported term tag {}", tag))),
+        };
+        Ok(term)
+    }
+
+    fn terms(&mut self, n: usize) -> Result<Vec<Term>, Error> {
+        // every term takes at least one byte, do not trust n for the allocation
+        let mut terms = Vec::with_capacity(n.min(self.data.len() - self.pos));
+        for _ in 0..n {
+            terms.push(self.term()?);
+        }
+        Ok(terms)
+    }
+}
@@ -0,0 +1,262 @@
+use crate::antidote_pb::CRDT_type;
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+
+/// Errors of the client and its transactions, to match on the failure kind (e.g. to retry only transient ones).
+/// Converts from and into `std::io::Error`: I/O errors become `Io`, io errors carrying an `AntidoteError`
+/// (e.g. of the protocol layer in `coder`) give it back. Use `AntidoteError::downcast` to look into an io error.
+#[derive(Debug)]
+pub enum AntidoteError {
+    /// An I/O error, e.g. `UnexpectedEof` when Antidote closed the connection or `ConnectionRefused`, an invalid argument
+    /// (`InvalidInput`) rejected before anything was sent, or a value the client cannot use (`InvalidData`).
+    Io(Error),
+    /// No connection to any host could be checked out before the checkout deadline passed, because no connection
+    /// could be opened to the hosts (or they are quarantined): an availability issue.
+    /// Lists each host address with its last error.
+    NoHealthyHosts(Vec<(String, String)>),
+    /// A client was built without any host.
+    NoHostsConfigured,
+    /// Antidote answered an operation with an error code that does not abort the transaction in a known way
+    /// (e.g. 0, `unknown`, for an update that does not fit the type of its object). See `TransactionAborted` for the others.
+    /// `message` is the reason Antidote gave, if it answered with an error response (e.g. for a request it failed to handle).
+    OperationFailed { code: ErrorCode, message: Option<String> },
+    /// A response came with another message code (`got`) than the one of the response to the request (`expected`),
+    /// it belongs to another request: responses and requests on the connection are out of sync. The connection is discarded.
+    UnexpectedMessageCode { expected: u8, got: u8 },
+    /// Antidote aborted the transaction. Retryable aborts may succeed when the whole transaction is run again.
+    ///
+    /// | code | Antidote error   | retryable | reason                                              |
+    /// |------|------------------|-----------|-----------------------------------------------------|
+    /// | 1    | `timeout`        | yes       | the transaction timed out on the server             |
+    /// | 2    | `no_permissions` | no        | red transaction did not get the requested locks     |
+    /// | 3    | `aborted`        | yes       | certification failed due to a concurrent write      |
+    ///
+    /// Other codes (e.g. 0, `unknown`) are returned as `OperationFailed`.
+    TransactionAborted { code: ErrorCode, retryable: bool },
+    /// The client's limit of open transactions (`ClientBuilder::max_open_transactions`) was reached
+    /// and no transaction finished in time.
+    TransactionLimitReached(usize),
+    /// All connections of the pools were in use and the client is set to fail fast
+    /// (`ClientBuilder::pool_exhausted_mode`).
+    PoolExhausted,
+    /// The hosts are reachable, but all connections of their pools stayed in use until the checkout gave up after the given
+    /// duration (each host is waited for at most its connection timeout, all of them at most the checkout deadline):
+    /// a capacity issue, the pools are too small for the load or connections are held too long.
+    PoolTimeout(Duration),
+    /// Responses and requests on the connection are out of sync for another reason than an `UnexpectedMessageCode`.
+    /// The connection is discarded.
+    StreamDesync(DesyncReason),
+    /// A read response holds another number of objects (`received`) than the read requested (`requested`),
+    /// so the values cannot be matched to the objects they belong to.
+    ProtocolMismatch { requested: usize, received: usize },
+    /// An object of a CRDT type the client cannot convert into a value, e.g. bounded counters (read with `CRDTReader::read_bcounter`)
+    /// or a type of a newer Antidote version returned in a field this client does not know.
+    UnsupportedCrdtType(CRDT_type),
+    /// An update sent without awaiting its response (`TransactionBuilder::update_no_ack`) failed, found when its
+    /// response was read later. `update` is the position of the failed call to `update` in the transaction
+    /// (counting from 0, all updates of the transaction included), `source` the error it failed with.
+    DeferredUpdateFailed { update: usize, source: Box<AntidoteError> },
+    /// An update of the transaction failed before (`DeferredUpdateFailed`), so it can only be aborted; run it again.
+    TransactionPoisoned,
+    /// A commit of the transaction failed before (or its response was lost), so it cannot be committed; run it again.
+    PreviousCommitFailed,
+    /// A map read has no entry of the given key and type (`MapReadResultExtractor`, `MapReadResult::get_many`),
+    /// `RRMAP` stands for a nested map of either kind.
+    EntryNotFound { key: Vec<u8>, crdt_type: CRDT_type },
+    /// A read or update would take the transaction beyond its limit of operations (the given number,
+    /// `TransactionBuilder::max_operations`). The operation was not sent, the transaction can still be committed or aborted.
+    TransactionTooLarge(usize),
+    /// Committing a transaction of `Client::commit_all` failed with `source`. The transactions before it were committed
+    /// (`commit_times`, so the failed one is at position `commit_times.len()`), the ones after it were aborted.
+    PartialCommit { commit_times: Vec<Vec<u8>>, source: Box<AntidoteError> },
+}
+
+/// Why responses and requests on a connection are out of sync, see `AntidoteError::StreamDesync`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DesyncReason {
+    /// A frame without message code, every message of Antidote starts with one.
+    EmptyFrame,
+    /// The responses to the given number of requests sent before were not read.
+    ResponsesPending(usize),
+    /// The response to a previous request was not read completely, e.g. its read failed.
+    IncompleteResponse,
+}
+
+impl fmt::Display for DesyncReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DesyncReason::EmptyFrame => write!(f, "Received an empty frame without message code"),
+            DesyncReason::ResponsesPending(pending) => write!(f, "the responses to {} previous requests were not received", pending),
+            DesyncReason::IncompleteResponse => write!(f, "the response to a previous request was not read completely"),
+        }
+    }
+}
+
+/// Error codes Antidote answers failed operations with (`antidote_pb_codec`), see `AntidoteError::TransactionAborted`.
+/// Codes this client does not know, e.g. of a newer Antidote version, are kept as `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    Unknown,
+    Timeout,
+    NoPermissions,
+    Aborted,
+    Other(u32),
+}
+
+impl ErrorCode {
+    /// The numeric code as sent by Antidote.
+    pub fn value(&self) -> u32 {
+        match self {
+            ErrorCode::Unknown => 0,
+            ErrorCode::Timeout => 1,
+            ErrorCode::NoPermissions => 2,
+            ErrorCode::Aborted => 3,
+            ErrorCode::Other(code) => *code,
+        }
+    }
+}
+
+impl From<u32> for ErrorCode {
+    fn from(code: u32) -> ErrorCode {
+        match code {
+            0 => ErrorCode::Unknown,
+            1 => ErrorCode::Timeout,
+            2 => ErrorCode::NoPermissions,
+            3 => ErrorCode::Aborted,
+            code => ErrorCode::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ErrorCode::Unknown => "unknown",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::NoPermissions => "no_permissions",
+            ErrorCode::Aborted => "aborted",
+            ErrorCode::Other(_) => "unrecognized",
+        };
+        write!(f, "{} (error code {})", name, self.value())
+    }
+}
+
+impl fmt::Display for AntidoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AntidoteError::Io(e) => write!(f, "{}", e),
+            AntidoteError::NoHealthyHosts(hosts) => {
+                write!(f, "No healthy hosts")?;
+                for (host, error) in hosts.iter() {
+                    write!(f, "; {}: {}", host, error)?;
+                }
+                Ok(())
+            }
+            AntidoteError::NoHostsConfigured => write!(f, "No hosts configured, add at least one Antidote server"),
+            AntidoteError::TransactionAborted { code, retryable } => {
+                write!(f, "Transaction aborted by Antidote; {} (retryable: {})", code, retryable)
+            }
+            AntidoteError::TransactionLimitReached(limit) => write!(f, "Limit of {} open transactions reached", limit),
+            AntidoteError::PoolExhausted => write!(f, "All pooled connections are in use"),
+            AntidoteError::PoolTimeout(waited) => write!(f, "All pooled connections stayed in use for {:?}", waited),
+            AntidoteError::OperationFailed { code, message: None } => write!(f, "operation not successful; {}", code),
+            AntidoteError::OperationFailed { code, message: Some(message) } => {
+                write!(f, "operation not successful; {}: {}", code, message)
+            }
+            AntidoteError::UnexpectedMessageCode { expected, got } => write!(f, "Invalid message code: {}. Expected {}.", got, expected),
+            AntidoteError::StreamDesync(reason) => write!(f, "Response stream out of sync: {}", reason),
+            AntidoteError::ProtocolMismatch { requested, received } => {
+                write!(f, "Read of {} objects returned {} values", requested, received)
+            }
+            AntidoteError::UnsupportedCrdtType(crdt_type) => write!(f, "Reading objects of type {:?} is not supported by the client", crdt_type),
+            AntidoteError::DeferredUpdateFailed { update, source } => write!(f, "Update {} of the transaction failed: {}", update, source),
+            AntidoteError::TransactionPoisoned => {
+                write!(f, "transaction can only be aborted: a previous update failed, run the transaction again")
+            }
+            AntidoteError::PreviousCommitFailed => {
+                write!(f, "transaction cannot be committed: a previous commit failed, run the transaction again")
+            }
+            AntidoteError::EntryNotFound { key, crdt_type } => {
+                write!(f, "{:?} entry with key {} not found", crdt_type, String::from_utf8_lossy(key))
+            }
+            AntidoteError::TransactionTooLarge(limit) => write!(f, "Transaction reached its limit of {} operations", limit),
+            AntidoteError::PartialCommit { commit_times, source } => {
+                write!(f, "Commit of transaction {} failed after committing the ones before it: {}", commit_times.len(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AntidoteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AntidoteError::Io(e) => Some(e),
+            AntidoteError::DeferredUpdateFailed { source, .. } => Some(source),
+            AntidoteError::PartialCommit { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl AntidoteError {
+    /// Returns the client error wrapped in an io error, if there is one.
+    pub fn downcast(e: &Error) -> Option<&AntidoteError> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<AntidoteError>())
+    }
+
+    /// The `ErrorKind` of the io error this error converts into, the one of the I/O error for `Io`.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            AntidoteError::Io(ref e) => e.kind(),
+            AntidoteError::NoHealthyHosts(_) => ErrorKind::NotConnected,
+            AntidoteError::NoHostsConfigured => ErrorKind::InvalidInput,
+            AntidoteError::TransactionAborted { .. } => ErrorKind::Other,
+            AntidoteError::TransactionLimitReached(_) => ErrorKind::WouldBlock,
+            AntidoteError::PoolExhausted => ErrorKind::WouldBlock,
+            AntidoteError::PoolTimeout(_) => ErrorKind::TimedOut,
+            AntidoteError::OperationFailed { .. } => ErrorKind::Other,
+            AntidoteError::UnexpectedMessageCode { .. } => ErrorKind::InvalidData,
+            AntidoteError::StreamDesync(_) => ErrorKind::InvalidData,
+            AntidoteError::ProtocolMismatch { .. } => ErrorKind::InvalidData,
+            AntidoteError::UnsupportedCrdtType(_) => ErrorKind::Unsupported,
+            AntidoteError::DeferredUpdateFailed { ref source, .. } => source.kind(),
+            AntidoteError::TransactionPoisoned => ErrorKind::Other,
+            AntidoteError::PreviousCommitFailed => ErrorKind::Other,
+            AntidoteError::EntryNotFound { .. } => ErrorKind::NotFound,
+            AntidoteError::TransactionTooLarge(_) => ErrorKind::Other,
+            AntidoteError::PartialCommit { ref source, .. } => source.kind(),
+        }
+    }
+
+    // Error for an operation Antidote answered with an error code.
+    #[cfg(feature = "net")]
+    pub(crate) fn operation_failed(code: u32) -> AntidoteError {
+        match ErrorCode::from(code) {
+            code @ (ErrorCode::Timeout | ErrorCode::Aborted) => AntidoteError::TransactionAborted { code, retryable: true },
+            code @ ErrorCode::NoPermissions => AntidoteError::TransactionAborted { code, retryable: false },
+            code => AntidoteError::OperationFailed { code, message: None },
+        }
+    }
+}
+
+impl From<AntidoteError> for Error {
+    fn from(e: AntidoteError) -> Error {
+        match e {
+            AntidoteError::Io(e) => e,
+            e => Error::new(e.kind(), e),
+        }
+    }
+}
+
+impl From<Error> for AntidoteError {
+    fn from(e: Error) -> AntidoteError {
+        if AntidoteError::downcast(&e).is_none() {
+            return AntidoteError::Io(e);
+        }
+        // checked above, the error wraps an AntidoteError
+        *e.into_inner().unwrap().downcast::<AntidoteError>().unwrap()
+    }
+}
@@ -0,0 +1,69 @@
+use crate::error::AntidoteError;
+
+use std::io::Error;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+
+// What start_transaction does when the limit of open transactions is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionLimitMode {
+    // Wait until a transaction finishes, at most for the checkout deadline.
+    Block,
+    // Fail right away with AntidoteError::TransactionLimitReached.
+    FailFast,
+}
+
+// Semaphore capping the interactive transactions open at once on a client.
+// Each transaction holds a permit until it is committed, aborted or dropped.
+pub(crate) struct TransactionLimit {
+    max: usize,
+    mode: TransactionLimitMode,
+    open: Mutex<usize>,
+    released: Condvar,
+}
+
+impl TransactionLimit {
+    pub(crate) fn new(max: usize, mode: TransactionLimitMode) -> TransactionLimit {
+        TransactionLimit {
+            max,
+            mode,
+            open: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn open(&self) -> usize {
+        *self.open.lock().unwrap()
+    }
+
+    pub(crate) fn acquire(self: &Arc<Self>, deadline: Duration) -> Result<Permit, Error> {
+        let start = Instant::now();
+        let mut open = self.open.lock().unwrap();
+        while *open >= self.max {
+            let remaining = deadline.checked_sub(start.elapsed()).unwrap_or_default();
+            if self.mode == TransactionLimitMode::FailFast || remaining == Duration::from_secs(0) {
+                return Err(AntidoteError::TransactionLimitReached(self.max).into());
+            }
+            open = self.released.wait_timeout(open, remaining).unwrap().0;
+        }
+        *open += 1;
+        Ok(Permit { limit: self.clone() })
+    }
+
+    fn release(&self) {
+        *self.open.lock().unwrap() -= 1;
+        self.released.notify_one();
+    }
+}
+
+// Slot of an open transaction, freed when dropped.
+pub(crate) struct Permit {
+    limit: Arc<TransactionLimit>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limit.release();
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(feature = "net")]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "net")]
+use std::time::Instant;
+
+
+/// The client operations reported to a `LatencyObserver`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Checkout,
+    StartTransaction,
+    Read,
+    Update,
+    Commit,
+    Abort,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Checkout => "checkout",
+            Operation::StartTransaction => "start_transaction",
+            Operation::Read => "read",
+            Operation::Update => "update",
+            Operation::Commit => "commit",
+            Operation::Abort => "abort",
+        }
+    }
+}
+
+/// Hook to observe how long the operations of a client take, e.g. to export metrics or log slow requests.
+/// Set it with `ClientBuilder::latency_observer`.
+pub trait LatencyObserver: Send + Sync {
+    fn observe(&self, op: Operation, elapsed: Duration, success: bool);
+
+    /// Like `observe`, with the trace id of the transaction the operation belongs to, if it has one
+    /// (see `TransactionBuilder::trace_id`). Defaults to `observe`, override it to correlate operations.
+    fn observe_traced(&self, op: Operation, elapsed: Duration, success: bool, trace_id: Option<&str>) {
+        let _ = trace_id;
+        self.observe(op, elapsed, success);
+    }
+}
+
+// Reports the time passed since start to the observer, if there is one.
+#[cfg(feature = "net")]
+pub(crate) fn observe(observer: &Option<Arc<dyn LatencyObserver>>, op: Operation, start: Instant, success: bool, trace_id: Option<&str>) {
+    if let Some(o) = observer {
+        o.observe_traced(op, start.elapsed(), success, trace_id);
+    }
+}
+
+/// Observer recording the client's operations with the `metrics` crate facade (feature `metrics`).
+/// Installed by default when the feature is enabled, so any installed recorder
+/// (e.g. `metrics-exporter-prometheus`) picks up:
+/// - `antidote_client_transactions_started_total`, `_committed_total`, `_aborted_total` (counters)
+/// - `antidote_client_operation_duration_seconds` labeled by `operation` and `success` (histogram)
+/// - `antidote_client_pool_checkout_duration_seconds` (histogram)
+#[cfg(feature = "metrics")]
+pub struct MetricsObserver;
+
+#[cfg(feature = "metrics")]
+impl LatencyObserver for MetricsObserver {
+    fn observe(&self, op: Operation, elapsed: Duration, success: bool) {
+        if op == Operation::Checkout {
+            metrics::histogram!("antidote_client_pool_checkout_duration_seconds").record(elapsed.as_secs_f64());
+            return;
+        }
+        if success {
+            match op {
+                Operation::StartTransaction => metrics::counter!("antidote_client_transactions_started_total").increment(1),
+                Operation::Commit => metrics::counter!("antidote_client_transactions_committed_total").increment(1),
+                Operation::Abort => metrics::counter!("antidote_client_transactions_aborted_total").increment(1),
+                _ => {}
+            }
+        }
+        metrics::histogram!("antidote_client_operation_duration_seconds",
+            "operation" => op.as_str(), "success" => if success { "true" } else { "false" })
+            .record(elapsed.as_secs_f64());
+    }
+}
+
+/// Why the pool dropped a connection instead of keeping it for the next checkout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscardReason {
+    /// Reading or writing on the connection failed, or it was returned with unanswered requests.
+    Broken,
+    /// The client closes every returned connection (`ClientBuilder::no_pool` and overflow connections).
+    SingleUse,
+}
+
+/// Hook to observe the lifecycle of pooled connections, e.g. to find out why a pool opens more
+/// connections than expected. Set it with `ClientBuilder::connection_event_listener`
+/// (or `AntidoteConnectionManager::with_event_listener` for pre-built pools).
+/// All methods default to doing nothing and get the 'host:port' address of the connection's host.
+///
+/// Events are reported from the connection manager, which r2d2 consults at these points only:
+/// `on_checked_out` requires `test_on_check_out` (the default), and connections r2d2 closes
+/// because of `idle_timeout` or `max_lifetime` are dropped without an event.
+pub trait ConnectionEventListener: Send + Sync {
+    /// A new connection was opened.
+    fn on_created(&self, host: &str) {
+        let _ = host;
+    }
+
+    /// Opening a new connection failed, r2d2 retries in the background.
+    fn on_connect_failed(&self, host: &str, error: &str) {
+        let _ = (host, error);
+    }
+
+    /// A pooled connection is about to be handed out.
+    fn on_checked_out(&self, host: &str) {
+        let _ = host;
+    }
+
+    /// A connection was returned and stays in the pool.
+    fn on_returned(&self, host: &str) {
+        let _ = host;
+    }
+
+    /// A connection was returned and gets closed instead of being pooled again.
+    fn on_discarded(&self, host: &str, reason: DiscardReason) {
+        let _ = (host, reason);
+    }
+}
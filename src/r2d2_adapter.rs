@@ -1,8 +1,12 @@
+use std::any::Any;
 use std::fmt;
-use std::{thread, time};
-use std::net::{TcpStream};
-
-use super::{CONNECT_RETRY_PERIOD};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::error::{AntidoteError, DesyncReason};
+use crate::observer::{ConnectionEventListener, DiscardReason};
 
 
 // r2d2 pool error definition
@@ -18,39 +22,355 @@ impl fmt::Display for PoolError {
 impl ::std::error::Error for PoolError {
 }
 impl PoolError {
-    fn _new(msg: &str) -> PoolError {
+    fn new(msg: &str) -> PoolError {
         PoolError {
             message: String::from(msg),
         }
     }
 }
 
+// A byte stream to an Antidote server: a TcpStream by default, any Read + Write stream with a custom Connector.
+pub trait Transport: Read + Write + Send {
+    // The stream as Any, e.g. to reach the TcpStream of a TCP connection for changing its options.
+    fn as_any(&self) -> &dyn Any;
+}
+impl<T: Read + Write + Send + 'static> Transport for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// Opens the stream of a new connection to the given 'host:port' address, replaces the default TCP connect,
+// e.g. to inject an in-memory stream in tests.
+pub type Connector = Arc<dyn Fn(&str) -> io::Result<Box<dyn Transport>> + Send + Sync>;
+
+// A connection to an Antidote server.
+// Remembers whether reading or writing on the stream failed, such a connection is broken
+// and gets discarded by the pool instead of being handed out again.
+// The same goes for a connection with an unfinished request (see `exchange`) or with responses not read yet
+// (see `send`): unread bytes of a response would be taken for the response to the next request.
+pub struct Connection {
+    stream: Box<dyn Transport>,
+    // timeouts set with `set_timeout` that were not restored yet
+    timeouts_set: usize,
+    broken: bool,
+    in_flight: bool,
+    // requests sent with `send` whose responses were not received yet
+    pending: usize,
+}
+impl Connection {
+    pub fn is_broken(&self) -> bool {
+        self.broken || self.in_flight || self.pending > 0 || self.timeouts_set > 0
+    }
+
+    // Makes the pool discard the connection when it is returned.
+    pub(crate) fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+
+    // The stream of a TCP connection. Goes through the trait object, the Box is a Transport of its own.
+    fn tcp_stream(&self) -> Option<&TcpStream> {
+        (*self.stream).as_any().downcast_ref::<TcpStream>()
+    }
+
+    // Sets a read and write timeout on the socket, returns the previous ones to hand to `restore_timeouts`.
+    // Only TCP connections support timeouts. A connection returned to the pool before its timeouts are restored
+    // (e.g. after a panic) is discarded.
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) -> io::Result<(Option<Duration>, Option<Duration>)> {
+        let socket = match self.tcp_stream() {
+            Some(socket) => socket,
+            None => return Err(io::Error::new(io::ErrorKind::Unsupported, "Timeouts are only supported on TCP connections")),
+        };
+        let previous = (socket.read_timeout()?, socket.write_timeout()?);
+        let result = socket.set_read_timeout(Some(timeout)).and_then(|()| socket.set_write_timeout(Some(timeout)));
+        // counted also if setting them failed, a timeout may have been set
+        self.timeouts_set += 1;
+        result.map(|()| previous)
+    }
+
+    // Restores the timeouts replaced by `set_timeout`, the connection is discarded if they cannot be restored.
+    pub(crate) fn restore_timeouts(&mut self, (read_timeout, write_timeout): (Option<Duration>, Option<Duration>)) {
+        let restored = self.tcp_stream()
+            .is_some_and(|socket| socket.set_read_timeout(read_timeout).is_ok() && socket.set_write_timeout(write_timeout).is_ok());
+        if restored {
+            self.timeouts_set -= 1;
+        }
+    }
+
+    // Sends a request and reads its response with the given function, so every request is matched by exactly one response.
+    // If it fails or never returns (e.g. an interrupted read), the response may be left partially read and
+    // the connection is out of sync. A response not matching the request (AntidoteError::StreamDesync,
+    // AntidoteError::UnexpectedMessageCode) breaks it as well.
+    pub(crate) fn exchange<T>(&mut self, request: impl FnOnce(&mut Connection) -> io::Result<T>) -> io::Result<T> {
+        // misuse of the connection by the client, not a failure of the host: it stays unusable while responses are pending
+        if self.pending > 0 {
+            return Err(AntidoteError::StreamDesync(DesyncReason::ResponsesPending(self.pending)).into());
+        }
+        self.tracked(request)
+    }
+
+    // Sends a request without reading its response, which has to be read with `receive` before any other exchange.
+    // Responses arrive in the order of the requests.
+    pub(crate) fn send(&mut self, request: impl FnOnce(&mut Connection) -> io::Result<()>) -> io::Result<()> {
+        self.pending += 1;
+        self.tracked(request)
+    }
+
+    // Reads the response to the oldest request sent with `send`.
+    pub(crate) fn receive<T>(&mut self, response: impl FnOnce(&mut Connection) -> io::Result<T>) -> io::Result<T> {
+        let result = self.tracked(response)?;
+        self.pending -= 1;
+        Ok(result)
+    }
+
+    // Sends a request and/or reads a response, the connection counts as in flight until it succeeds.
+    fn tracked<T>(&mut self, request: impl FnOnce(&mut Connection) -> io::Result<T>) -> io::Result<T> {
+        // the connection stays in flight, so the pool discards it without counting a failure of the host
+        if self.in_flight {
+            return Err(AntidoteError::StreamDesync(DesyncReason::IncompleteResponse).into());
+        }
+        self.in_flight = true;
+        let result = request(self).inspect_err(|e| {
+            match AntidoteError::downcast(e) {
+                Some(AntidoteError::StreamDesync(_)) | Some(AntidoteError::UnexpectedMessageCode { .. }) => self.broken = true,
+                // an error response of Antidote, read completely
+                Some(AntidoteError::OperationFailed { .. }) => self.in_flight = false,
+                _ => {}
+            }
+        })?;
+        self.in_flight = false;
+        Ok(result)
+    }
+}
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                // end of stream: Antidote closed the connection
+                self.broken = true;
+                Ok(0)
+            }
+            Ok(n) => Ok(n),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::Interrupted {
+                    self.broken = true;
+                }
+                Err(e)
+            }
+        }
+    }
+}
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.stream.write(buf);
+        if let Err(e) = &result {
+            if e.kind() != io::ErrorKind::Interrupted {
+                self.broken = true;
+            }
+        }
+        result
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.stream.flush();
+        if result.is_err() {
+            self.broken = true;
+        }
+        result
+    }
+}
+
+// Socket options of new connections, None keeps the OS default.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SocketOptions {
+    pub(crate) send_buffer_size: Option<usize>,
+    pub(crate) recv_buffer_size: Option<usize>,
+    // None waits as long as the OS does (often more than a minute for an unreachable host)
+    pub(crate) connect_timeout: Option<Duration>,
+}
+
+// Connects to the address with the given socket options, trying each address it resolves to.
+// The buffer sizes are set before connecting, so the receive buffer size is taken into account for the TCP window.
+fn connect_tcp(addr: &str, options: SocketOptions) -> io::Result<TcpStream> {
+    if options.send_buffer_size.is_none() && options.recv_buffer_size.is_none() && options.connect_timeout.is_none() {
+        return TcpStream::connect(addr);
+    }
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, format!("Could not resolve {}", addr));
+    for socket_addr in addr.to_socket_addrs()? {
+        let socket = socket2::Socket::new(socket2::Domain::for_address(socket_addr), socket2::Type::STREAM, Some(socket2::Protocol::TCP))?;
+        if let Some(size) = options.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = options.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        let result = match options.connect_timeout {
+            Some(timeout) => socket.connect_timeout(&socket_addr.into(), timeout),
+            None => socket.connect(&socket_addr.into()),
+        };
+        match result {
+            Ok(()) => return Ok(socket.into()),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+// Circuit breaker of a host: after failure_threshold consecutive failures (failed connects or broken connections)
+// the host is quarantined for quarantine_period. A failure after the quarantine quarantines it again right away,
+// a connection returned intact reinstates it. A failure_threshold of 0 disables the quarantine.
+// Checkouts skip a quarantined host, the probing is left to r2d2: it keeps reconnecting a pool below its min_idle
+// connections in the background (retrying at most every connection_timeout / 2), and a successful connect ends
+// the quarantine early. Pools keeping no idle connections (min_idle Some(0), no_pool, overflow pools) are not probed,
+// their host is only tried again by the first checkout after the quarantine period.
+pub(crate) struct HostHealth {
+    failure_threshold: u32,
+    quarantine_period: Duration,
+    failures: AtomicU32,
+    // start of the quarantine; the end is not stored as an Instant, a huge period would overflow the clock
+    quarantined_since: Mutex<Option<Instant>>,
+}
+impl HostHealth {
+    pub(crate) fn new(failure_threshold: u32, quarantine_period: Duration) -> HostHealth {
+        HostHealth {
+            failure_threshold,
+            quarantine_period,
+            failures: AtomicU32::new(0),
+            quarantined_since: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
+    pub(crate) fn quarantine_period(&self) -> Duration {
+        self.quarantine_period
+    }
+
+    pub(crate) fn is_quarantined(&self) -> bool {
+        match *self.quarantined_since.lock().unwrap() {
+            Some(since) => since.elapsed() < self.quarantine_period,
+            None => false,
+        }
+    }
+
+    fn record_failure(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst).saturating_add(1);
+        if self.failure_threshold > 0 && failures >= self.failure_threshold {
+            *self.quarantined_since.lock().unwrap() = Some(Instant::now());
+        }
+    }
+
+    // The host accepted a connection again: end the quarantine, a failure quarantines it again right away
+    // until a connection is returned intact.
+    fn record_connected(&self) {
+        *self.quarantined_since.lock().unwrap() = None;
+    }
+
+    fn record_success(&self) {
+        if self.failures.swap(0, Ordering::SeqCst) > 0 {
+            *self.quarantined_since.lock().unwrap() = None;
+        }
+    }
+}
+
 // r2d2 connection manager definition
 pub struct AntidoteConnectionManager {
     addr: String,
+    connector: Option<Connector>,
+    health: Option<Arc<HostHealth>>,
+    socket_options: SocketOptions,
+    single_use: bool,
+    listener: Option<Arc<dyn ConnectionEventListener>>,
 }
 impl AntidoteConnectionManager {
     pub fn new(addr: String) -> AntidoteConnectionManager {
         AntidoteConnectionManager {
-            addr
+            addr,
+            connector: None,
+            health: None,
+            socket_options: SocketOptions::default(),
+            single_use: false,
+            listener: None,
         }
     }
+
+    // Connection manager opening its connections with the given connector instead of a TCP connect.
+    pub fn with_connector(addr: String, connector: Connector) -> AntidoteConnectionManager {
+        AntidoteConnectionManager {
+            addr,
+            connector: Some(connector),
+            health: None,
+            socket_options: SocketOptions::default(),
+            single_use: false,
+            listener: None,
+        }
+    }
+
+    // Reports failed connects and broken connections to the circuit breaker of the host.
+    pub(crate) fn with_health(mut self, health: Arc<HostHealth>) -> AntidoteConnectionManager {
+        self.health = Some(health);
+        self
+    }
+
+    pub(crate) fn with_socket_options(mut self, socket_options: SocketOptions) -> AntidoteConnectionManager {
+        self.socket_options = socket_options;
+        self
+    }
+
+    // Lets the pool drop (and close) every connection when it is returned instead of reusing it.
+    pub(crate) fn with_single_use(mut self, single_use: bool) -> AntidoteConnectionManager {
+        self.single_use = single_use;
+        self
+    }
+
+    // Reports connections being created, checked out, returned and discarded to the listener.
+    pub fn with_event_listener(mut self, listener: Arc<dyn ConnectionEventListener>) -> AntidoteConnectionManager {
+        self.listener = Some(listener);
+        self
+    }
 }
 impl r2d2::ManageConnection for AntidoteConnectionManager {
 
-    type Connection = TcpStream;
+    type Connection = Connection;
     type Error = PoolError;
 
     fn connect(&self) -> Result<Self::Connection, Self::Error> {
         // let conn = TcpStream::connect(self.addr.clone()).unwrap();
         // Ok(conn)
-        if let Ok(conn) = TcpStream::connect(self.addr.clone()) {
-            Ok(conn)
-        } else {
-            thread::sleep(time::Duration::from_millis(CONNECT_RETRY_PERIOD));
-            // Err(PoolError::new("Connection invalid"))
-            // I guess thats a dangerous recursive retry? ^.^
-            self.connect()
+        // The address is resolved again on every connect, so connections replacing broken ones
+        // follow a host name that now points to a new IP (e.g. a rescheduled pod behind a load balancer).
+        // A failed connect is not retried here: r2d2 retries in the background with a growing delay
+        // and reports the last error when a checkout times out.
+        let stream = match &self.connector {
+            Some(connector) => connector(&self.addr),
+            None => connect_tcp(&self.addr, self.socket_options).map(|s| Box::new(s) as Box<dyn Transport>),
+        };
+        match stream {
+            Ok(stream) => {
+                if let Some(health) = &self.health {
+                    health.record_connected();
+                }
+                if let Some(listener) = &self.listener {
+                    listener.on_created(&self.addr);
+                }
+                Ok(Connection {
+                    stream,
+                    timeouts_set: 0,
+                    broken: false,
+                    in_flight: false,
+                    pending: 0,
+                })
+            }
+            Err(e) => {
+                if let Some(health) = &self.health {
+                    health.record_failure();
+                }
+                if let Some(listener) = &self.listener {
+                    listener.on_connect_failed(&self.addr, &e.to_string());
+                }
+                Err(PoolError::new(format!("Could not connect to {}: {}", self.addr, e).as_str()))
+            }
         }
     }
     fn is_valid(&self, _conn: &mut Self::Connection) -> Result<(), Self::Error> {
@@ -69,9 +389,31 @@ impl r2d2::ManageConnection for AntidoteConnectionManager {
 
         // Well we will just get an error while trying to write on the stream if the connection is dead 
         // and antidote will handle invalid calls and return an error that is captured in the coder as well...
+        if let Some(listener) = &self.listener {
+            listener.on_checked_out(&self.addr);
+        }
         Ok(())
     }
-    fn has_broken(&self, _conn: &mut Self::Connection) -> bool {
-        false
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        let broken = conn.is_broken();
+        // only failed I/O and responses out of sync count against the host; connections left unusable by the client
+        // (an interrupted exchange, unread responses, timeouts not restored) are discarded without affecting its health
+        if let Some(health) = &self.health {
+            if conn.broken {
+                health.record_failure();
+            } else if !broken {
+                health.record_success();
+            }
+        }
+        if let Some(listener) = &self.listener {
+            if broken {
+                listener.on_discarded(&self.addr, DiscardReason::Broken);
+            } else if self.single_use {
+                listener.on_discarded(&self.addr, DiscardReason::SingleUse);
+            } else {
+                listener.on_returned(&self.addr);
+            }
+        }
+        broken || self.single_use
     }
 }
\ No newline at end of file
@@ -1,24 +1,113 @@
 use crate::antidote_pb::*;
 use crate::coder;
+use crate::error::AntidoteError;
+use crate::observer::{self, LatencyObserver, Operation};
+use crate::limit::Permit;
+use crate::descriptor::{self, TransactionDescriptor};
 use super::{Client, AntidoteConnectionManager};
 
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt;
-use protobuf::{RepeatedField};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use byteorder::{ByteOrder, BigEndian};
+use protobuf::{Message, ProtobufEnum, RepeatedField};
 use std::io::{Error, ErrorKind};
 
+// Responses to updates sent with `TransactionBuilder::update_no_ack` left unread at most. Unread responses fill the
+// socket buffers, once they are full Antidote stops reading requests and sending more updates would block.
+const MAX_DEFERRED_UPDATES: usize = 1000;
 
 /// Represents a bucket in the Antidote database.
 /// Offers a high-level interface to issue read and write operations on objects in the bucket.
 pub struct Bucket {
     pub bucket : Vec<u8>,
+    // private so that adding settings does not break code building a bucket, create one with `Bucket::new`
+    types: Option<Arc<TypeRegistry>>,
+}
+
+impl Bucket {
+    pub fn new(bucket: Vec<u8>) -> Bucket {
+        Bucket { bucket, types: None }
+    }
+
+    /// Sets the registry the `*_registered` methods look up the types of keys in.
+    /// Take an `Arc` to share one schema between buckets.
+    pub fn with_types(mut self, types: Arc<TypeRegistry>) -> Bucket {
+        self.types = Some(types);
+        self
+    }
+
+    /// Types of the bucket's objects, used by the `*_registered` methods to look up the type of a key.
+    pub fn types(&self) -> Option<&Arc<TypeRegistry>> {
+        self.types.as_ref()
+    }
+
+    /// Type registered for the key, fails if the bucket has no registry or the key matches no entry.
+    pub fn registered_type(&self, key: &Key) -> Result<CRDT_type, AntidoteError> {
+        match self.types.as_ref().and_then(|t| t.lookup(key)) {
+            Some(crdt_type) => Ok(crdt_type),
+            None => Err(Error::new(ErrorKind::InvalidInput, format!("no registered type for key {}", key)).into()),
+        }
+    }
+}
+
+/// Maps keys to their CRDT types, to keep the schema of an application in one place.
+/// Keys are matched exactly first, then by the longest registered prefix.
+#[derive(Debug, Clone, Default)]
+pub struct TypeRegistry {
+    keys: HashMap<Vec<u8>, CRDT_type>,
+    prefixes: Vec<(Vec<u8>, CRDT_type)>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> TypeRegistry {
+        TypeRegistry::default()
+    }
+
+    /// Registers the type of a single key.
+    pub fn key(mut self, key: Vec<u8>, crdt_type: CRDT_type) -> TypeRegistry {
+        self.keys.insert(key, crdt_type);
+        self
+    }
+
+    /// Registers the type of all keys starting with the prefix.
+    pub fn prefix(mut self, prefix: Vec<u8>, crdt_type: CRDT_type) -> TypeRegistry {
+        self.prefixes.retain(|(p, _)| *p != prefix);
+        self.prefixes.push((prefix, crdt_type));
+        self
+    }
+
+    pub fn lookup(&self, key: &Key) -> Option<CRDT_type> {
+        if let Some(crdt_type) = self.keys.get(&key.0) {
+            return Some(*crdt_type);
+        }
+        self.prefixes.iter()
+            .filter(|(prefix, _)| key.0.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, crdt_type)| *crdt_type)
+    }
 }
 
 /// A transaction object offers low-level mechanisms to send protocol-buffer messages to Antidote in the context of
 /// a highly-available transaction.
 /// Typical representatives are interactive transactions handled by Antidote and static transactions handled on the client side.
 pub trait Transaction {
-    fn read(&mut self, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, Error>;
-    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), Error>;
+    fn read(&mut self, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, AntidoteError>;
+    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), AntidoteError>;
+    /// Commits the transaction, so code written against the trait can finalize any transaction.
+    /// A no-op by default: static transactions commit each operation right away.
+    fn commit(&mut self) -> Result<(), AntidoteError> {
+        Ok(())
+    }
+    /// Aborts the transaction. A no-op by default: operations of static transactions are already committed.
+    fn abort(&mut self) -> Result<(), AntidoteError> {
+        Ok(())
+    }
 }
 
 /// Type alias for byte-slices.
@@ -32,82 +121,450 @@ impl fmt::Display for Key {
 }
 
 
+/// Marker types for the CRDT type of a `TypedKey`.
+pub enum Counter {}
+pub enum BCounter {}
+pub enum Set {}
+pub enum Reg {}
+pub enum MVReg {}
+pub enum Map {}
+
+/// A key bound to the CRDT type of its object, e.g. `TypedKey<Counter>`.
+/// Readers and update operations only accept typed keys of the matching type,
+/// so reading a set as a counter is caught at compile time instead of failing at runtime.
+/// The untyped `Key` is accepted for any type.
+///
+/// LWW and multi-value registers are distinct types, a register written with `mv_reg_put`
+/// can not be read with `read_reg`:
+///
+/// ```compile_fail
+/// use antidote_rust_client::transactions::{Bucket, CRDTReader, MVReg, Transaction, TypedKey};
+/// fn read(bucket: &Bucket, tx: &mut dyn Transaction) {
+///     let key: TypedKey<MVReg> = TypedKey::new("key".as_bytes().to_vec());
+///     bucket.read_reg(tx, &key);
+/// }
+/// ```
+///
+/// and a register written with `reg_put` can not be read with `read_mv_reg`:
+///
+/// ```compile_fail
+/// use antidote_rust_client::transactions::{Bucket, CRDTReader, Reg, Transaction, TypedKey};
+/// fn read(bucket: &Bucket, tx: &mut dyn Transaction) {
+///     let key: TypedKey<Reg> = TypedKey::new("key".as_bytes().to_vec());
+///     bucket.read_mv_reg(tx, &key);
+/// }
+/// ```
+///
+/// For untyped keys the register readers check the type of the returned value at runtime.
+pub struct TypedKey<T> {
+    pub key: Key,
+    crdt_type: PhantomData<T>,
+}
+impl<T> TypedKey<T> {
+    pub fn new(key: Vec<u8>) -> TypedKey<T> {
+        TypedKey {
+            key: Key(key),
+            crdt_type: PhantomData,
+        }
+    }
+}
+impl<T> Clone for TypedKey<T> {
+    fn clone(&self) -> TypedKey<T> {
+        TypedKey::new(self.key.0.clone())
+    }
+}
+impl<T> fmt::Debug for TypedKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TypedKey({:?})", self.key.0)
+    }
+}
+
+/// Keys usable for objects of the CRDT type T.
+pub trait KeyFor<T> {
+    fn key(&self) -> &Key;
+}
+impl<T> KeyFor<T> for Key {
+    fn key(&self) -> &Key {
+        self
+    }
+}
+impl<T> KeyFor<T> for TypedKey<T> {
+    fn key(&self) -> &Key {
+        &self.key
+    }
+}
+// keys shared between threads or stored on the heap
+impl<T, K: KeyFor<T>> KeyFor<T> for Arc<K> {
+    fn key(&self) -> &Key {
+        (**self).key()
+    }
+}
+impl<T, K: KeyFor<T>> KeyFor<T> for Rc<K> {
+    fn key(&self) -> &Key {
+        (**self).key()
+    }
+}
+impl<T, K: KeyFor<T>> KeyFor<T> for Box<K> {
+    fn key(&self) -> &Key {
+        (**self).key()
+    }
+}
+
+/// The kinds of map CRDTs offered by Antidote.
+/// - AddWins (`CRDT_type::RRMAP`): entries can be removed; removing an entry resets the nested CRDT,
+///   concurrent updates to a removed entry win over the remove.
+/// - GrowOnly (`CRDT_type::GMAP`): entries can never be removed, which keeps the map state smaller.
+///   Remove updates are invalid on grow-only maps and are rejected by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKind {
+    AddWins,
+    GrowOnly,
+}
+impl MapKind {
+    pub fn crdt_type(&self) -> CRDT_type {
+        match self {
+            MapKind::AddWins => CRDT_type::RRMAP,
+            MapKind::GrowOnly => CRDT_type::GMAP,
+        }
+    }
+}
+
 /// Represents the result of reading from a map object.
 /// Grants access to the keys of the map to access values of the nested CRDTs.
+#[derive(Debug, Clone)]
 pub struct MapReadResult {
     pub map_resp: ApbGetMapResp,
 }
 
+/// Target contents of a map for `MapReadResult::diff_to`, the value of each nested entry by key and type.
+pub type DesiredMap = BTreeMap<MapEntryKey, CRDTValue>;
+
+/// Value of a nested CRDT of a map.
+#[derive(Debug, Clone)]
+pub enum CRDTValue {
+    Counter(i32),
+    Set(Vec<Vec<u8>>),
+    Reg(Vec<u8>),
+    MVReg(Vec<Vec<u8>>),
+    Map(MapReadResult),
+    Flag(bool),
+}
+
 // A transaction handled by Antidote on the server side.
 // Interactive Transactions need to be started on the server and are kept open for their duration.
 // Update operations are only visible to reads issued in the context of the same transaction or after committing the transaction.
-// Always commit or abort interactive transactions to clean up the server side!
+// Always commit or abort interactive transactions to clean up the server side! Dropping an unfinished one aborts it.
+// After a failed update it is unknown which updates of the batch Antidote applied, so the transaction only allows an abort:
+// reads, updates and commit fail from then on.
 pub struct InteractiveTransaction {
     pub tx_id: Vec<u8>,
     // pub conn: Connection,
     // pub conn: TcpStream,
     pub conn: r2d2::PooledConnection<AntidoteConnectionManager>,
+    /// Set once Antidote confirmed the commit; committing again is then a no-op.
     pub committed: bool,
+    // set when a commit was attempted but not confirmed, the transaction cannot be committed anymore
+    pub(crate) commit_failed: bool,
+    // set when an update failed, the transaction can only be aborted then
+    pub(crate) poisoned: bool,
+    // set once an abort was sent, the transaction is not aborted again when dropped
+    pub(crate) aborted: bool,
+    // slot of the client's limit of open transactions, released when the transaction finishes
+    pub(crate) permit: Option<Permit>,
+    pub(crate) observer: Option<Arc<dyn LatencyObserver>>,
+    pub(crate) trace_id: Option<String>,
+    // counters read in this transaction by (bucket, key, type) like Antidote identifies objects, see `read_counter_projected`
+    pub(crate) counters: HashMap<(Vec<u8>, Vec<u8>, CRDT_type), CounterProjection>,
+    // send updates without awaiting their responses, see `TransactionBuilder::update_no_ack`
+    pub(crate) no_ack: bool,
+    // number of calls to update so far
+    pub(crate) updates_sent: usize,
+    // positions of the updates whose responses were not read yet, oldest first
+    pub(crate) deferred: VecDeque<usize>,
+    // number of reads and updates sent so far, and their limit, see `TransactionBuilder::max_operations`
+    pub(crate) operations: usize,
+    pub(crate) max_operations: Option<usize>,
+}
+
+// Value of a counter as last read in a transaction, and the increments applied to it since.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CounterProjection {
+    base: i32,
+    delta: i64,
+}
+
+/// Result of `InteractiveTransaction::commit_detailed`.
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    /// Commit time (vector clock) returned by Antidote, empty if the transaction was already committed.
+    pub commit_time: Vec<u8>,
+    /// Measured duration of the commit round-trip to the server.
+    pub duration: Duration,
 }
 
 impl Transaction for InteractiveTransaction {
 
-    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), Error> {
+    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), AntidoteError> {
+        let start = Instant::now();
+        let result = self.do_update(updates);
+        observer::observe(&self.observer, Operation::Update, start, result.is_ok(), self.trace_id.as_deref());
+        result
+    }
+
+    fn read(&mut self, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, AntidoteError> {
+        let start = Instant::now();
+        let result = self.do_read(objects);
+        observer::observe(&self.observer, Operation::Read, start, result.is_ok(), self.trace_id.as_deref());
+        result
+    }
+
+    fn commit(&mut self) -> Result<(), AntidoteError> {
+        InteractiveTransaction::commit(self)
+    }
+
+    fn abort(&mut self) -> Result<(), AntidoteError> {
+        InteractiveTransaction::abort(self)
+    }
+
+}
+
+impl fmt::Debug for InteractiveTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InteractiveTransaction")
+            .field("tx_id", &self.descriptor_hex())
+            .field("committed", &self.committed)
+            .field("trace_id", &self.trace_id)
+            .finish()
+    }
+}
+
+impl InteractiveTransaction {
+
+    /// Trace id the transaction was started with, see `TransactionBuilder::trace_id`.
+    pub fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+
+    /// Number of reads and updates sent in this transaction so far (failed ones included, local projections not).
+    pub fn operation_count(&self) -> usize {
+        self.operations
+    }
+
+    /// The transaction descriptor (`tx_id`) as lowercase hex string, stable for the same descriptor bytes.
+    pub fn descriptor_hex(&self) -> String {
+        self.tx_id.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Decodes the transaction descriptor into the start time and the coordinating process on the server,
+    /// which appear in Antidote's logs. The descriptor has no snapshot clock.
+    /// The format is internal to Antidote, unknown formats fail with `ErrorKind::InvalidData`.
+    pub fn descriptor(&self) -> Result<TransactionDescriptor, AntidoteError> {
+        Ok(descriptor::parse_transaction(&self.tx_id)?)
+    }
+
+    /// Commits the transaction. Antidote acknowledges once it is committed in the local DC,
+    /// it is replicated to other DCs asynchronously (see the README on commit durability).
+    pub fn commit(&mut self) -> Result<(), AntidoteError> {
+        self.commit_detailed().map(|_| ())
+    }
+
+    /// Reads a counter like `CRDTReader::read_counter`, but without a round-trip if the counter was already read in
+    /// this transaction: the value is then projected locally from the value read and the increments applied since.
+    ///
+    /// The projection is only used when the base value was fetched in this transaction, so it reflects the
+    /// transaction's snapshot exactly like a server read would. Counters never read in the transaction,
+    /// or updated with anything but increments since the last read, are read from Antidote.
+    pub fn read_counter_projected<K: KeyFor<Counter>>(&mut self, bucket: &Bucket, key: &K) -> Result<i32, AntidoteError> {
+        if let Some(projection) = self.counters.get(&(bucket.bucket.clone(), key.key().0.clone(), CRDT_type::COUNTER)) {
+            return Ok((i64::from(projection.base) + projection.delta) as i32);
+        }
+        bucket.read_counter(self, key)
+    }
+
+    /// Runs operations of the transaction (reads, updates, commit) with a timeout for each of them, e.g. to bound
+    /// a single slow read: `tx.with_timeout(Duration::from_millis(100), |tx| bucket.read_counter(tx, &key))`.
+    /// The timeout applies to every read from and write to the connection; the previous timeouts are restored afterwards,
+    /// also if an operation fails. A timed out operation fails with `ErrorKind::WouldBlock` or `ErrorKind::TimedOut`
+    /// and leaves the connection out of sync, the transaction cannot continue and is best aborted.
+    /// Fails with `ErrorKind::Unsupported` on connections of a custom `Connector` not returning a `TcpStream`.
+    pub fn with_timeout<T>(&mut self, timeout: Duration, op: impl FnOnce(&mut InteractiveTransaction) -> Result<T, AntidoteError>) -> Result<T, AntidoteError> {
+        let previous = self.conn.set_timeout(timeout)?;
+        let mut guard = TimeoutGuard { tx: self, previous };
+        op(&mut guard)
+    }
+
+    /// Commits like `commit`, additionally returning the commit time and the measured round-trip duration.
+    pub fn commit_detailed(&mut self) -> Result<CommitInfo, AntidoteError> {
+        let start = Instant::now();
+        let result = self.do_commit();
+        let duration = start.elapsed();
+        self.permit = None;
+        observer::observe(&self.observer, Operation::Commit, start, result.is_ok(), self.trace_id.as_deref());
+        result.map(|commit_time| CommitInfo { commit_time, duration })
+    }
+
+    pub fn abort(&mut self) -> Result<(), AntidoteError> {
+        let start = Instant::now();
+        let result = self.do_abort();
+        self.permit = None;
+        observer::observe(&self.observer, Operation::Abort, start, result.is_ok(), self.trace_id.as_deref());
+        result
+    }
+
+    fn do_update(&mut self, updates: &[ApbUpdateOp]) -> Result<(), AntidoteError> {
+        self.check_poisoned()?;
+        self.count_operation()?;
+        let position = self.updates_sent;
+        self.updates_sent += 1;
+        if self.deferred.len() >= MAX_DEFERRED_UPDATES {
+            self.receive_deferred()?;
+        }
+        // poisoned until Antidote confirms the updates: after a rejected update or a lost response it is unknown
+        // which updates of the batch were applied, committing could persist a partial batch
+        self.poisoned = true;
         let mut apb_update = ApbUpdateObjects::new();
         apb_update.set_updates(RepeatedField::from_vec(updates.to_vec()));
         apb_update.set_transaction_descriptor(self.tx_id.to_vec());
 
         // apb_update.encode(self.conn.get_mut_ref())?;
         // let resp: ApbOperationResp = decode_operation_resp(self.conn.get_mut_ref())?;
-        apb_update.encode(&mut *self.conn)?;
-        let resp: ApbOperationResp = coder::decode_operation_resp(&mut *self.conn)?;
-        if !resp.get_success() {
-            return Err(Error::new(ErrorKind::Other, format!("operation not successful; error code {}", resp.get_errorcode())))
+        if self.no_ack {
+            self.conn.send(|c| apb_update.encode(c))?;
+            self.deferred.push_back(position);
+        } else {
+            let resp: ApbOperationResp = self.conn.exchange(|c| { apb_update.encode(c)?; coder::decode_operation_resp(c) })?;
+            if !resp.get_success() {
+                return Err(AntidoteError::operation_failed(resp.get_errorcode()))
+            }
         }
+        self.poisoned = false;
+        self.project_updates(updates);
         Ok(())
     }
 
-    fn read(&mut self, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, Error> {
+    // Reads the responses to the updates sent without awaiting them, in the order they were sent.
+    // All of them are read to keep the connection in sync, the first failed update is returned.
+    fn receive_deferred(&mut self) -> Result<(), AntidoteError> {
+        if self.deferred.is_empty() {
+            return Ok(());
+        }
+        self.poisoned = true;
+        let mut failed = None;
+        while let Some(update) = self.deferred.pop_front() {
+            let resp = self.conn.receive(coder::decode_operation_resp)?;
+            if !resp.get_success() && failed.is_none() {
+                failed = Some(AntidoteError::DeferredUpdateFailed { update, source: Box::new(AntidoteError::operation_failed(resp.get_errorcode())) });
+            }
+        }
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        self.poisoned = false;
+        Ok(())
+    }
+
+    fn check_poisoned(&self) -> Result<(), AntidoteError> {
+        if self.poisoned {
+            return Err(AntidoteError::TransactionPoisoned);
+        }
+        Ok(())
+    }
+
+    fn count_operation(&mut self) -> Result<(), AntidoteError> {
+        if let Some(max) = self.max_operations {
+            if self.operations >= max {
+                return Err(AntidoteError::TransactionTooLarge(max))
+            }
+        }
+        self.operations += 1;
+        Ok(())
+    }
+
+    // Adds the increments to the projections of the counters read before; any other update drops the projection.
+    fn project_updates(&mut self, updates: &[ApbUpdateOp]) {
+        for update in updates.iter() {
+            let object = update.get_boundobject();
+            // a fat or bounded counter of the same bucket and key is another object
+            let id = (object.get_bucket().to_vec(), object.get_key().to_vec(), object.get_field_type());
+            let operation = update.get_operation();
+            match self.counters.get_mut(&id) {
+                Some(projection) if operation.has_counterop() => projection.delta += operation.get_counterop().get_inc(),
+                Some(_) => { self.counters.remove(&id); }
+                None => {}
+            }
+        }
+    }
+
+    // Remembers the counters read as base values for their projections. Values read include the updates of this
+    // transaction, so the increments start over from there.
+    fn project_reads(&mut self, objects: &[ApbBoundObject], resp: &ApbReadObjectsResp) {
+        for (object, value) in objects.iter().zip(resp.get_objects().iter()) {
+            if object.get_field_type() == CRDT_type::COUNTER && value.has_counter() {
+                let id = (object.get_bucket().to_vec(), object.get_key().to_vec(), object.get_field_type());
+                self.counters.insert(id, CounterProjection { base: value.get_counter().get_value(), delta: 0 });
+            }
+        }
+    }
+
+    fn do_read(&mut self, objects: &[ApbBoundObject]) -> Result<ApbReadObjectsResp, AntidoteError> {
+        self.check_poisoned()?;
+        self.count_operation()?;
+        self.receive_deferred()?;
         let mut apb_update = ApbReadObjects::new();
         apb_update.set_transaction_descriptor(self.tx_id.to_vec());
         apb_update.set_boundobjects(RepeatedField::from_vec(objects.to_vec()));
 
         // apb_update.encode(&mut self.conn.get_ref())?;
         // let result = decode_read_objects_resp(self.conn.get_mut_ref());
-        apb_update.encode(&mut *self.conn)?;
-        let result = coder::decode_read_objects_resp(&mut *self.conn);
-        return result;
+        let resp = self.conn.exchange(|c| { apb_update.encode(c)?; coder::decode_read_objects_resp(c) })?;
+        if !resp.get_success() {
+            return Err(AntidoteError::operation_failed(resp.get_errorcode()))
+        }
+        validate_read_response(objects.len(), &resp)?;
+        self.project_reads(objects, &resp);
+        Ok(resp)
     }
 
-}
-
-impl InteractiveTransaction {
-
-    pub fn commit(&mut self) -> Result<(), Error> {
-        if !self.committed {
-            let mut msg = ApbCommitTransaction::new();
-            msg.set_transaction_descriptor(self.tx_id.to_vec());
-            // msg.encode(self.conn.get_mut_ref())?;
-            msg.encode(&mut *self.conn)?;
-            // let op = decode_commit_resp(self.conn.get_mut_ref())?;
-            let op = coder::decode_commit_resp(&mut *self.conn)?;
-            // self.conn.close()?;
-            if !op.get_success() {
-                return Err(Error::new(ErrorKind::Other, format!("operation not successful; error code {}", op.get_errorcode())))
-            }
+    fn do_commit(&mut self) -> Result<Vec<u8>, AntidoteError> {
+        if self.committed {
+            return Ok(Vec::new());
         }
-        Ok(())
+        if self.commit_failed {
+            return Err(AntidoteError::PreviousCommitFailed);
+        }
+        self.check_poisoned()?;
+        self.receive_deferred()?;
+        // marked as failed until Antidote confirms the commit, so a lost response does not count as committed
+        self.commit_failed = true;
+        let mut msg = ApbCommitTransaction::new();
+        msg.set_transaction_descriptor(self.tx_id.to_vec());
+        // msg.encode(self.conn.get_mut_ref())?;
+        // let op = decode_commit_resp(self.conn.get_mut_ref())?;
+        let mut op = self.conn.exchange(|c| { msg.encode(c)?; coder::decode_commit_resp(c) })?;
+        // self.conn.close()?;
+        if !op.get_success() {
+            return Err(AntidoteError::operation_failed(op.get_errorcode()))
+        }
+        self.commit_failed = false;
+        self.committed = true;
+        Ok(op.take_commit_time())
     }
 
-    pub fn abort(&mut self) -> Result<(), Error> {
-        if !self.committed {
+    fn do_abort(&mut self) -> Result<(), AntidoteError> {
+        if !self.committed && !self.aborted {
+            self.aborted = true;
+            // the responses have to be read before the abort's, whether the updates failed does not matter anymore
+            if let Err(e) = self.receive_deferred() {
+                if !matches!(e, AntidoteError::DeferredUpdateFailed { .. }) {
+                    return Err(e);
+                }
+            }
             let mut msg = ApbAbortTransaction::new();
             msg.set_transaction_descriptor(self.tx_id.to_vec());
-            msg.encode(&mut *self.conn)?;
-            let op = coder::decode_operation_resp(&mut *self.conn)?;
+            let op = self.conn.exchange(|c| { msg.encode(c)?; coder::decode_operation_resp(c) })?;
             // self.conn.close()?;
             if !op.get_success() {
-                return Err(Error::new(ErrorKind::Other, format!("operation not successful; error code {}", op.get_errorcode())))
+                return Err(AntidoteError::operation_failed(op.get_errorcode()))
             }
         }
         Ok(())
@@ -115,14 +572,246 @@ impl InteractiveTransaction {
 
 }
 
+/// Aborts a transaction that was neither committed nor aborted, e.g. because the thread running it panicked,
+/// so it does not stay open on the server. If the abort fails (the connection may be left in any state by the panic),
+/// the connection is marked broken and the pool discards it instead of handing it out again.
+impl Drop for InteractiveTransaction {
+    fn drop(&mut self) {
+        if self.committed || self.commit_failed || self.aborted {
+            return;
+        }
+        // a connection left in flight or with unread responses is discarded anyway, without counting against the host
+        if self.abort().is_err() && !self.conn.is_broken() {
+            self.conn.mark_broken();
+        }
+    }
+}
+
+// Restores the timeouts replaced by `InteractiveTransaction::with_timeout` when dropped, also if the operation panics.
+struct TimeoutGuard<'a> {
+    tx: &'a mut InteractiveTransaction,
+    previous: (Option<Duration>, Option<Duration>),
+}
+
+impl Deref for TimeoutGuard<'_> {
+    type Target = InteractiveTransaction;
+
+    fn deref(&self) -> &InteractiveTransaction {
+        self.tx
+    }
+}
+
+impl DerefMut for TimeoutGuard<'_> {
+    fn deref_mut(&mut self) -> &mut InteractiveTransaction {
+        self.tx
+    }
+}
+
+impl Drop for TimeoutGuard<'_> {
+    fn drop(&mut self) {
+        self.tx.conn.restore_timeouts(self.previous);
+    }
+}
+
+/// Access mode of an interactive transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionMode {
+    ReadWrite,
+    ReadOnly,
+    WriteOnly,
+}
+
+/// Consistency of an interactive transaction.
+/// Blue transactions are highly available, red transactions are strongly consistent and need the configured locks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    Blue,
+    Red,
+}
+
+/// Composes the properties of an interactive transaction before starting it on the server.
+/// Created with `Client::transaction_builder`, the defaults match `Client::start_transaction`:
+/// read-write, blue, no snapshot timestamp and no locks.
+pub struct TransactionBuilder<'clt> {
+    client: &'clt Client,
+    mode: TransactionMode,
+    consistency: Consistency,
+    timestamp: Option<Vec<u8>>,
+    shared_locks: Vec<Vec<u8>>,
+    exclusive_locks: Vec<Vec<u8>>,
+    trace_id: Option<String>,
+    no_ack: bool,
+    max_operations: Option<usize>,
+}
+
+impl<'clt> TransactionBuilder<'clt> {
+    pub(crate) fn new(client: &'clt Client) -> TransactionBuilder<'clt> {
+        TransactionBuilder {
+            client,
+            mode: TransactionMode::ReadWrite,
+            consistency: Consistency::Blue,
+            timestamp: None,
+            shared_locks: Vec::new(),
+            exclusive_locks: Vec::new(),
+            trace_id: None,
+            no_ack: false,
+            max_operations: None,
+        }
+    }
+
+    pub fn mode(mut self, mode: TransactionMode) -> TransactionBuilder<'clt> {
+        self.mode = mode;
+        self
+    }
+
+    pub fn consistency(mut self, consistency: Consistency) -> TransactionBuilder<'clt> {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Snapshot the transaction has to read from at least, e.g. the commit time of a previous transaction.
+    pub fn timestamp(mut self, timestamp: Vec<u8>) -> TransactionBuilder<'clt> {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn shared_locks(mut self, locks: Vec<Vec<u8>>) -> TransactionBuilder<'clt> {
+        self.shared_locks = locks;
+        self
+    }
+
+    pub fn exclusive_locks(mut self, locks: Vec<Vec<u8>>) -> TransactionBuilder<'clt> {
+        self.exclusive_locks = locks;
+        self
+    }
+
+    /// Opaque id (e.g. of a distributed trace) the latency observer gets with every operation of the transaction.
+    /// Client-side metadata only, it is not sent to Antidote.
+    pub fn trace_id(mut self, trace_id: &str) -> TransactionBuilder<'clt> {
+        self.trace_id = Some(String::from(trace_id));
+        self
+    }
+
+    /// Sends the updates of the transaction without awaiting Antidote's response to each of them, saving a round-trip
+    /// per update, e.g. for bulk loads. The responses are read and checked before the next read and on commit or abort.
+    /// A failed update is then reported by that read or commit as `AntidoteError::DeferredUpdateFailed`, which tells
+    /// which update failed; as after any failed update the transaction can only be aborted.
+    /// At most 1000 responses are left unread, the next update reads them first.
+    pub fn update_no_ack(mut self, no_ack: bool) -> TransactionBuilder<'clt> {
+        self.no_ack = no_ack;
+        self
+    }
+
+    /// Limits the number of reads and updates of the transaction, e.g. to stop a runaway loop before it piles up
+    /// state on the server. Going beyond the limit fails with `AntidoteError::TransactionTooLarge` without sending
+    /// the operation; the transaction is left intact and can still be committed or aborted. No limit by default.
+    pub fn max_operations(mut self, max_operations: usize) -> TransactionBuilder<'clt> {
+        self.max_operations = Some(max_operations);
+        self
+    }
+
+    pub fn start(self) -> Result<InteractiveTransaction, AntidoteError> {
+        let start = Instant::now();
+        let observer = self.client.observer.clone();
+        let trace_id = self.trace_id.clone();
+        let result = self.do_start();
+        observer::observe(&observer, Operation::StartTransaction, start, result.is_ok(), trace_id.as_deref());
+        result
+    }
+
+    fn do_start(self) -> Result<InteractiveTransaction, AntidoteError> {
+        let permit = match &self.client.transaction_limit {
+            Some(limit) => Some(limit.acquire(self.client.checkout_deadline)?),
+            None => None,
+        };
+        let mut conn = self.client.get_connection_for(self.mode == TransactionMode::ReadOnly)?;
+        let read_write: u32 = match self.mode {
+            TransactionMode::ReadWrite => 0,
+            TransactionMode::ReadOnly => 1,
+            TransactionMode::WriteOnly => 2,
+        };
+        let red_blue: u32 = match self.consistency {
+            Consistency::Blue => 0,
+            Consistency::Red => 1,
+        };
+        let mut apb_txn_properties = ApbTxnProperties::new();
+        apb_txn_properties.set_read_write(read_write);
+        apb_txn_properties.set_red_blue(red_blue);
+        apb_txn_properties.set_shared_locks(RepeatedField::from_vec(self.shared_locks));
+        apb_txn_properties.set_exclusive_locks(RepeatedField::from_vec(self.exclusive_locks));
+        let mut apb_txn = ApbStartTransaction::new();
+        apb_txn.set_properties(apb_txn_properties);
+        if let Some(timestamp) = self.timestamp {
+            apb_txn.set_timestamp(timestamp);
+        }
+
+        let apb_txn_resp = conn.exchange(|c| { apb_txn.encode(c)?; coder::decode_start_transaction_resp(c) })?;
+
+        let txn_desc = apb_txn_resp.get_transaction_descriptor();
+        let tx = InteractiveTransaction {
+            conn,
+            tx_id: txn_desc.to_vec(),
+            committed: false,
+            commit_failed: false,
+            poisoned: false,
+            aborted: false,
+            permit,
+            observer: self.client.observer.clone(),
+            trace_id: self.trace_id,
+            counters: HashMap::new(),
+            no_ack: self.no_ack,
+            updates_sent: 0,
+            deferred: VecDeque::new(),
+            operations: 0,
+            max_operations: self.max_operations,
+        };
+        Ok(tx)
+    }
+}
+
 /// Pseudo transaction to issue reads and updated without starting an interactive transaction.
 /// Can be interpreted as starting a transaction for each read or update and directly committing it.
 pub struct StaticTransaction<'stlt> {
-    pub client: &'stlt mut Client,
+    pub client: &'stlt Client,
 }
 
 impl<'stlt> Transaction for StaticTransaction<'stlt> {
-    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), Error> {
+    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), AntidoteError> {
+        self.observed_update(updates).map(|_| ())
+    }
+    fn read(&mut self, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, AntidoteError> {
+        self.observed_read(objects, None)
+    }
+}
+
+impl<'stlt> StaticTransaction<'stlt> {
+    /// Applies the updates to a bucket, then reads the objects of `read` at a snapshot including the updates.
+    /// Two static calls run as separate transactions on the server and may read from different snapshots,
+    /// so a plain static read is not guaranteed to see a preceding static write.
+    /// Here the commit time returned for the update is used as the minimum snapshot of the read.
+    pub fn static_read_your_write(&mut self, bucket: &Bucket, updates: Vec<CRDTUpdate>, read: &MultiRead) -> Result<Vec<ApbReadObjectResp>, AntidoteError> {
+        let update_ops: Vec<ApbUpdateOp> = updates.iter().map(|u| u.convert_to_top_level(bucket.bucket.clone())).collect();
+        let commit_time = self.observed_update(&update_ops)?;
+        let mut resp = self.observed_read(&read.objects, Some(commit_time))?;
+        Ok(resp.take_objects().into_vec())
+    }
+
+    fn observed_update(&mut self, updates: &[ApbUpdateOp]) -> Result<Vec<u8>, AntidoteError> {
+        let start = Instant::now();
+        let result = self.do_update(updates);
+        observer::observe(&self.client.observer, Operation::Update, start, result.is_ok(), None);
+        result
+    }
+
+    fn observed_read(&mut self, objects: &[ApbBoundObject], timestamp: Option<Vec<u8>>) -> Result<ApbReadObjectsResp, AntidoteError> {
+        let start = Instant::now();
+        let result = self.do_read(objects, timestamp);
+        observer::observe(&self.client.observer, Operation::Read, start, result.is_ok(), None);
+        result
+    }
+
+    // Returns the commit time of the update.
+    fn do_update(&mut self, updates: &[ApbUpdateOp]) -> Result<Vec<u8>, AntidoteError> {
         let mut apb_start_transaction = ApbStartTransaction::new();
         apb_start_transaction.set_properties(ApbTxnProperties::new());
         let mut apb_static_update = ApbStaticUpdateObjects::new();
@@ -133,163 +822,323 @@ impl<'stlt> Transaction for StaticTransaction<'stlt> {
         let mut conn = self.client.get_connection()?;
         // apb_static_update.encode(con.get_mut_ref())?;
         // let resp: ApbCommitResp = decode_commit_resp(con.get_mut_ref())?;
-        apb_static_update.encode(&mut *conn)?;
-        let resp: ApbCommitResp = coder::decode_commit_resp(&mut *conn)?;
+        let mut resp: ApbCommitResp = conn.exchange(|c| { apb_static_update.encode(c)?; coder::decode_commit_resp(c) })?;
         // conn.close()?;
         if !resp.get_success() {
-            return Err(Error::new(ErrorKind::Other, format!("operation not successful; error code {}", resp.get_errorcode())))
+            return Err(AntidoteError::operation_failed(resp.get_errorcode()))
         }
-        Ok(())
+        Ok(resp.take_commit_time())
     }
-    fn read(&mut self, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, Error> {
+    // Reads at a snapshot including the given commit time, if there is one.
+    fn do_read(&mut self, objects: &[ApbBoundObject], timestamp: Option<Vec<u8>>) -> Result<ApbReadObjectsResp, AntidoteError> {
         let mut apb_start_transaction = ApbStartTransaction::new();
         apb_start_transaction.set_properties(ApbTxnProperties::new());
+        if let Some(timestamp) = timestamp {
+            apb_start_transaction.set_timestamp(timestamp);
+        }
         let mut apb_static_read = ApbStaticReadObjects::new();
         apb_static_read.set_transaction(apb_start_transaction);
         apb_static_read.set_objects(RepeatedField::from_vec(objects.to_vec()));
 
-        let mut conn = self.client.get_connection()?;
-        apb_static_read.encode(&mut *conn)?;
-        let sresp: ApbStaticReadObjectsResp = coder::decode_static_read_objects_resp(&mut *conn)?;
+        let mut conn = self.client.get_connection_for(true)?;
+        let sresp: ApbStaticReadObjectsResp = conn.exchange(|c| { apb_static_read.encode(c)?; coder::decode_static_read_objects_resp(c) })?;
         // con.close()?;
+        if !sresp.get_objects().get_success() {
+            return Err(AntidoteError::operation_failed(sresp.get_objects().get_errorcode()))
+        }
+        validate_read_response(objects.len(), sresp.get_objects())?;
         Ok(sresp.get_objects().clone())
     }
 }
 
 /// A CRDTReader allows to read the value of objects identified by keys in the context of a transaction.
 pub trait CRDTReader {
-    fn read_set(&self, tx: &mut dyn Transaction, key: &Key) -> Result<Vec<Vec<u8>>, Error>;
-    fn read_reg(&self, tx: &mut dyn Transaction, key: &Key) -> Result<Vec<u8>, Error>;
-    fn read_map(&self, tx: &mut dyn Transaction, key: &Key) -> Result<MapReadResult, Error>;
-    fn read_mv_reg(&self, tx: &mut dyn Transaction, key: &Key) -> Result<Vec<Vec<u8>>, Error>;
-    fn read_counter(&self, tx: &mut dyn Transaction, key: &Key) -> Result<i32, Error>;
+    /// Reads the elements of a set.
+    /// Note: Antidote only returns the elements (`ApbGetSetResp`), the add tags (dots) and tombstones of an ORSET
+    /// stay on the server, so the metadata behind a concurrent add and remove cannot be inspected from a client.
+    /// A remove only removes the adds visible in the snapshot of its transaction: an element added by a concurrent
+    /// transaction survives the remove, whichever of both commits last (add-wins).
+    fn read_set<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError>;
+    /// Reads a set and sorts its elements lexicographically.
+    /// An ORSET has no inherent order, the sorting is purely a client-side convenience for reproducible output.
+    fn read_set_sorted<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError>;
+    /// Reads the current value of a last-writer-wins register.
+    /// Note: Antidote's protocol-buffer interface only returns the value of a register (`ApbGetRegResp`),
+    /// the write timestamp used to resolve concurrent writes stays on the server and cannot be read by a client.
+    /// If you need the time of the last write, store it next to the value yourself (e.g. in a map).
+    fn read_reg<K: KeyFor<Reg>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<u8>, AntidoteError>;
+    /// Reads an add-wins map, same as `read_map_kind(tx, key, MapKind::AddWins)`.
+    fn read_map<K: KeyFor<Map>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<MapReadResult, AntidoteError>;
+    fn read_map_kind<K: KeyFor<Map>>(&self, tx: &mut dyn Transaction, key: &K, kind: MapKind) -> Result<MapReadResult, AntidoteError>;
+    fn read_mv_reg<K: KeyFor<MVReg>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError>;
+    fn read_counter<K: KeyFor<Counter>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<i32, AntidoteError>;
+    /// Reads the value of a bounded counter (`CRDT_type::BCOUNTER`), the increments minus the decrements of all replicas.
+    /// The value never drops below 0: Antidote rejects a decrement the replica does not hold enough rights for,
+    /// see `bcounter_dec`. The rights themselves are not exposed by the protocol.
+    fn read_bcounter<K: KeyFor<BCounter>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<i64, AntidoteError>;
 }
 
 // TODO: I am pretty sure all that boxing is NOT what you SHOULD do..
 impl CRDTReader for Bucket {
-    fn read_set(&self, tx: &mut dyn Transaction, key: &Key) -> Result<Vec<Vec<u8>>, Error> {
+    fn read_set<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError> {
         let crdt_type = CRDT_type::ORSET;
         let mut apb_bound_object = ApbBoundObject::new();
         apb_bound_object.set_bucket(self.bucket.clone());
-        apb_bound_object.set_key(key.0.clone());
+        apb_bound_object.set_key(key.key().0.clone());
         apb_bound_object.set_field_type(crdt_type);
 
         let mut objects = Vec::new();
         objects.push(apb_bound_object);
-        let resp = tx.read(&objects)?;
+        let resp = read_validated(tx, &objects)?;
 
         let val : &[Vec<u8>] = resp.get_objects()[0].get_set().get_value();
         Ok((*val).to_vec())
     }
-    fn read_reg(&self, tx: &mut dyn Transaction, key: &Key) -> Result<Vec<u8>, Error> {
+    fn read_set_sorted<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError> {
+        let mut val = self.read_set(tx, key)?;
+        val.sort();
+        Ok(val)
+    }
+    fn read_reg<K: KeyFor<Reg>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<u8>, AntidoteError> {
         let crdt_type = CRDT_type::LWWREG;
         let mut apb_bound_object = ApbBoundObject::new();
         apb_bound_object.set_bucket(self.bucket.clone());
-        apb_bound_object.set_key(key.0.clone());
+        apb_bound_object.set_key(key.key().0.clone());
         apb_bound_object.set_field_type(crdt_type);
 
         let mut objects = Vec::new();
         objects.push(apb_bound_object);
-        let resp = tx.read(&objects)?;
+        let resp = read_validated(tx, &objects)?;
 
-        let val : &[u8] = resp.get_objects()[0].get_reg().get_value();
-        Ok((*val).to_vec())
+        let object = read_object(&resp, key.key(), CRDT_type::LWWREG)?;
+        Ok(object.get_reg().get_value().to_vec())
     }
-    fn read_map(&self, tx: &mut dyn Transaction, key: &Key) -> Result<MapReadResult, Error> {
-        let crdt_type = CRDT_type::RRMAP;
+    fn read_map<K: KeyFor<Map>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<MapReadResult, AntidoteError> {
+        self.read_map_kind(tx, key, MapKind::AddWins)
+    }
+    fn read_map_kind<K: KeyFor<Map>>(&self, tx: &mut dyn Transaction, key: &K, kind: MapKind) -> Result<MapReadResult, AntidoteError> {
+        let crdt_type = kind.crdt_type();
         let mut apb_bound_object = ApbBoundObject::new();
         apb_bound_object.set_bucket(self.bucket.clone());
-        apb_bound_object.set_key(key.0.clone());
+        apb_bound_object.set_key(key.key().0.clone());
         apb_bound_object.set_field_type(crdt_type);
         
         let mut objects = Vec::new();
         objects.push(apb_bound_object);
-        let resp = tx.read(&objects)?;
+        let resp = read_validated(tx, &objects)?;
 
         let val = MapReadResult {
             map_resp: (*(resp.get_objects()[0].get_map())).clone() // hmm ... TOCO ?
         };
         Ok(val)
     }
-    fn read_mv_reg(&self, tx: &mut dyn Transaction, key: &Key) -> Result<Vec<Vec<u8>>, Error> {
+    fn read_mv_reg<K: KeyFor<MVReg>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError> {
         let crdt_type = CRDT_type::MVREG;
         let mut apb_bound_object = ApbBoundObject::new();
         apb_bound_object.set_bucket(self.bucket.clone());
-        apb_bound_object.set_key(key.0.clone());
+        apb_bound_object.set_key(key.key().0.clone());
         apb_bound_object.set_field_type(crdt_type);
         
         let mut objects = Vec::new();
         objects.push(apb_bound_object);
-        let resp = tx.read(&objects)?;
+        let resp = read_validated(tx, &objects)?;
 
-        let val = resp.get_objects()[0].get_mvreg().get_values();
-        Ok((*val).to_vec())
+        let object = read_object(&resp, key.key(), CRDT_type::MVREG)?;
+        Ok(object.get_mvreg().get_values().to_vec())
     }
-    fn read_counter(&self, tx: &mut dyn Transaction, key: &Key) -> Result<i32, Error> {
+    fn read_counter<K: KeyFor<Counter>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<i32, AntidoteError> {
         let crdt_type = CRDT_type::COUNTER;
         let mut apb_bound_object = ApbBoundObject::new();
         apb_bound_object.set_bucket(self.bucket.clone());
-        apb_bound_object.set_key(key.0.clone());
+        apb_bound_object.set_key(key.key().0.clone());
         apb_bound_object.set_field_type(crdt_type);
         
         let mut objects = Vec::new();
         objects.push(apb_bound_object);
-        let resp = tx.read(&objects)?;
+        let resp = read_validated(tx, &objects)?;
 
         let val = resp.get_objects()[0].get_counter().get_value();
         Ok(val)
     }
+    fn read_bcounter<K: KeyFor<BCounter>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<i64, AntidoteError> {
+        let mut apb_bound_object = ApbBoundObject::new();
+        apb_bound_object.set_bucket(self.bucket.clone());
+        apb_bound_object.set_key(key.key().0.clone());
+        apb_bound_object.set_field_type(CRDT_type::BCOUNTER);
+
+        let objects = vec!(apb_bound_object);
+        let resp = read_validated(tx, &objects)?;
+
+        Ok(i64::from(resp.get_objects()[0].get_counter().get_value()))
+    }
+}
+
+// Fails with AntidoteError::ProtocolMismatch unless the response holds one object per requested object,
+// the readers rely on it to match the values to the objects by position.
+pub(crate) fn validate_read_response(requested: usize, resp: &ApbReadObjectsResp) -> Result<(), AntidoteError> {
+    let received = resp.get_objects().len();
+    if received != requested {
+        return Err(AntidoteError::ProtocolMismatch { requested, received })
+    }
+    Ok(())
+}
+
+// Reads through any Transaction, including custom ones, checking the response like the built-in transactions do.
+fn read_validated(tx: &mut dyn Transaction, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, AntidoteError> {
+    let resp = tx.read(objects)?;
+    validate_read_response(objects.len(), &resp)?;
+    Ok(resp)
+}
+
+// Returns the single object of a read response, failing if it does not hold a value of the requested register type,
+// e.g. because Antidote answered for a register of the other kind.
+fn read_object<'r>(resp: &'r ApbReadObjectsResp, key: &Key, crdt_type: CRDT_type) -> Result<&'r ApbReadObjectResp, AntidoteError> {
+    let object = match resp.get_objects().first() {
+        Some(o) => o,
+        None => return Err(Error::new(ErrorKind::InvalidData, format!("no value returned for {}", key)).into()),
+    };
+    let matches = match crdt_type {
+        CRDT_type::LWWREG => object.has_reg(),
+        CRDT_type::MVREG => object.has_mvreg(),
+        _ => true,
+    };
+    if !matches {
+        return Err(Error::new(ErrorKind::InvalidData, format!("type mismatch: {} was read as {:?} but Antidote returned a value of another type", key, crdt_type)).into())
+    }
+    Ok(object)
+}
+
+/// Collects objects of possibly different buckets to read all of them with a single request.
+/// The responses are returned in the order the objects were added.
+pub struct MultiRead {
+    objects: Vec<ApbBoundObject>,
+}
+
+impl Default for MultiRead {
+    fn default() -> MultiRead {
+        MultiRead::new()
+    }
+}
+
+impl MultiRead {
+    pub fn new() -> MultiRead {
+        MultiRead {
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn add(mut self, bucket: &Bucket, key: &Key, crdt_type: CRDT_type) -> MultiRead {
+        let mut apb_bound_object = ApbBoundObject::new();
+        apb_bound_object.set_bucket(bucket.bucket.clone());
+        apb_bound_object.set_key(key.0.clone());
+        apb_bound_object.set_field_type(crdt_type);
+        self.objects.push(apb_bound_object);
+        self
+    }
+
+    /// Adds an object with the type registered for its key in the bucket, see `Bucket::with_types`.
+    pub fn add_registered(self, bucket: &Bucket, key: &Key) -> Result<MultiRead, AntidoteError> {
+        let crdt_type = bucket.registered_type(key)?;
+        Ok(self.add(bucket, key, crdt_type))
+    }
+
+    pub fn read(&self, tx: &mut dyn Transaction) -> Result<Vec<ApbReadObjectResp>, AntidoteError> {
+        let mut resp = read_validated(tx, &self.objects)?;
+        Ok(resp.take_objects().into_vec())
+    }
+
+    // Reads the objects like `read`, converting each value according to the type the object was added with.
+    pub(crate) fn read_values(&self, tx: &mut dyn Transaction) -> Result<Vec<CRDTValue>, AntidoteError> {
+        self.read(tx)?.iter().zip(self.objects.iter())
+            .map(|(object, requested)| read_crdt_value(requested.get_field_type(), object))
+            .collect()
+    }
+
+    /// Reads the objects like `read`, returning each value together with its type as reported by Antidote
+    /// (see `probe_type`), e.g. to render objects without knowing their types in advance.
+    pub fn read_typed(&self, tx: &mut dyn Transaction) -> Result<Vec<(CRDT_type, CRDTValue)>, AntidoteError> {
+        let mut values = Vec::new();
+        for (object, requested) in self.read(tx)?.iter().zip(self.objects.iter()) {
+            match probe_type(object) {
+                Some(t) => values.push((t, read_crdt_value(t, object)?)),
+                // the value is in a field unknown to this client, e.g. of a type added in a newer Antidote version
+                None if object.unknown_fields.iter().next().is_some() => {
+                    return Err(AntidoteError::UnsupportedCrdtType(requested.get_field_type()))
+                }
+                None => return Err(Error::new(ErrorKind::InvalidData, "read object holds no value of a known type").into()),
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// Reads a counter sharded across several buckets under the same key with a single request and returns the sum of the shards.
+pub fn read_counter_across(tx: &mut dyn Transaction, buckets: &[Bucket], key: &Key) -> Result<i64, AntidoteError> {
+    let read = buckets.iter().fold(MultiRead::new(), |read, bucket| read.add(bucket, key, CRDT_type::COUNTER));
+    Ok(read.read(tx)?.iter().map(|o| i64::from(o.get_counter().get_value())).sum())
+}
+
+/// Reads a set sharded across several buckets under the same key with a single request and returns the union of the shards,
+/// sorted lexicographically.
+/// There is no variant for registers: which shard was written last cannot be told, the write timestamps stay on the server.
+pub fn read_set_across(tx: &mut dyn Transaction, buckets: &[Bucket], key: &Key) -> Result<Vec<Vec<u8>>, AntidoteError> {
+    let read = buckets.iter().fold(MultiRead::new(), |read, bucket| read.add(bucket, key, CRDT_type::ORSET));
+    let mut elems: Vec<Vec<u8>> = read.read(tx)?.iter().flat_map(|o| o.get_set().get_value().iter().cloned()).collect();
+    elems.sort();
+    elems.dedup();
+    Ok(elems)
 }
 
 pub trait MapReadResultExtractor {
-    fn set(&self, key: &Key) -> Result<Vec<Vec<u8>>, Error>;
-    fn reg(&self, key: &Key) -> Result<Vec<u8>, Error>;
-    fn map(&self, key: &Key) -> Result<MapReadResult, Error>;
-    fn mv_reg(&self, key: &Key) -> Result<Vec<Vec<u8>>, Error>;
-    fn counter(&self, key: &Key) -> Result<i32, Error>;
+    fn set<K: KeyFor<Set>>(&self, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError>;
+    fn reg<K: KeyFor<Reg>>(&self, key: &K) -> Result<Vec<u8>, AntidoteError>;
+    fn map<K: KeyFor<Map>>(&self, key: &K) -> Result<MapReadResult, AntidoteError>;
+    fn mv_reg<K: KeyFor<MVReg>>(&self, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError>;
+    fn counter<K: KeyFor<Counter>>(&self, key: &K) -> Result<i32, AntidoteError>;
     fn list_map_keys(&self) -> Vec<MapEntryKey>;
 }
 
 impl MapReadResultExtractor for MapReadResult {
-    fn set(&self, key: &Key) -> Result<Vec<Vec<u8>>, Error> {
+    fn set<K: KeyFor<Set>>(&self, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError> {
         for (_, me) in self.map_resp.get_entries().iter().enumerate() {
-            if me.get_key().get_field_type() == CRDT_type::ORSET && me.get_key().get_key() == key.0 {
+            if me.get_key().get_field_type() == CRDT_type::ORSET && me.get_key().get_key() == key.key().0 {
                 return Ok((*(me.get_value().get_set().get_value())).to_vec());
             }
         }
-        Err(Error::new(ErrorKind::Other, format!("set entry with key {} not found", key)))
+        Err(AntidoteError::EntryNotFound { key: key.key().0.clone(), crdt_type: CRDT_type::ORSET })
     }
-    fn reg(&self, key: &Key) -> Result<Vec<u8>, Error> {
+    fn reg<K: KeyFor<Reg>>(&self, key: &K) -> Result<Vec<u8>, AntidoteError> {
         for (_, me) in self.map_resp.get_entries().iter().enumerate() {
-            if me.get_key().get_field_type() == CRDT_type::LWWREG && me.get_key().get_key() == key.0 {
+            if me.get_key().get_field_type() == CRDT_type::LWWREG && me.get_key().get_key() == key.key().0 {
                 return Ok((*(me.get_value().get_reg().get_value())).to_vec());
             }
         }
-        Err(Error::new(ErrorKind::Other, format!("register entry with key {} not found", key)))
+        Err(AntidoteError::EntryNotFound { key: key.key().0.clone(), crdt_type: CRDT_type::LWWREG })
     }
-    fn map(&self, key: &Key) -> Result<MapReadResult, Error> {
+    fn map<K: KeyFor<Map>>(&self, key: &K) -> Result<MapReadResult, AntidoteError> {
         for (_, me) in self.map_resp.get_entries().iter().enumerate() {
-            if me.get_key().get_field_type() == CRDT_type::RRMAP && me.get_key().get_key() == key.0 {
+            let crdt_type = me.get_key().get_field_type();
+            if (crdt_type == CRDT_type::RRMAP || crdt_type == CRDT_type::GMAP) && me.get_key().get_key() == key.key().0 {
                 return Ok(MapReadResult {map_resp: (*(me.get_value().get_map())).clone()});
             }
         }
-        Err(Error::new(ErrorKind::Other, format!("map entry with key {} not found", key)))
+        Err(AntidoteError::EntryNotFound { key: key.key().0.clone(), crdt_type: CRDT_type::RRMAP })
     }
-    fn mv_reg(&self, key: &Key) -> Result<Vec<Vec<u8>>, Error> {
+    fn mv_reg<K: KeyFor<MVReg>>(&self, key: &K) -> Result<Vec<Vec<u8>>, AntidoteError> {
         for (_, me) in self.map_resp.get_entries().iter().enumerate() {
-            if me.get_key().get_field_type() == CRDT_type::MVREG && me.get_key().get_key() == key.0 {
+            if me.get_key().get_field_type() == CRDT_type::MVREG && me.get_key().get_key() == key.key().0 {
                 return Ok((*(me.get_value().get_mvreg().get_values())).to_vec());
             }
         }
-        Err(Error::new(ErrorKind::Other, format!("mvreg entry with key {} not found", key)))
+        Err(AntidoteError::EntryNotFound { key: key.key().0.clone(), crdt_type: CRDT_type::MVREG })
     }
-    fn counter(&self, key: &Key) -> Result<i32, Error> {
+    fn counter<K: KeyFor<Counter>>(&self, key: &K) -> Result<i32, AntidoteError> {
         for (_, me) in self.map_resp.get_entries().iter().enumerate() {
-            if me.get_key().get_field_type() == CRDT_type::COUNTER && me.get_key().get_key() == key.0 {
+            if me.get_key().get_field_type() == CRDT_type::COUNTER && me.get_key().get_key() == key.key().0 {
                 return Ok(me.get_value().get_counter().get_value());
             }
         }
-        Err(Error::new(ErrorKind::Other, format!("register entry with key {} not found", key)))
+        Err(AntidoteError::EntryNotFound { key: key.key().0.clone(), crdt_type: CRDT_type::COUNTER })
     }
 
     fn list_map_keys(&self) -> Vec<MapEntryKey> {
@@ -304,11 +1153,155 @@ impl MapReadResultExtractor for MapReadResult {
     }
 }
 
+impl MapReadResult {
+    /// Returns the keys and values of all nested entries of the given type, in one scan of the map,
+    /// e.g. all counters of a map used as a dictionary of counters.
+    pub fn entries_of_type(&self, crdt_type: CRDT_type) -> Vec<(Vec<u8>, CRDTValue)> {
+        let mut entries = Vec::new();
+        for me in self.map_resp.get_entries().iter() {
+            if me.get_key().get_field_type() != crdt_type {
+                continue;
+            }
+            if let Some(value) = crdt_value(crdt_type, me.get_value()) {
+                entries.push((me.get_key().get_key().to_vec(), value));
+            }
+        }
+        entries
+    }
+
+    /// Looks up several nested entries by key and type with a single scan of the map,
+    /// instead of one scan per entry like the `MapReadResultExtractor` methods.
+    /// Returns the values in the order of `entries`, an error for each entry not found in the map.
+    pub fn get_many(&self, entries: &[(Key, CRDT_type)]) -> Vec<Result<CRDTValue, AntidoteError>> {
+        let index: HashMap<(CRDT_type, &[u8]), &ApbReadObjectResp> = self.map_resp.get_entries().iter()
+            .map(|me| ((me.get_key().get_field_type(), me.get_key().get_key()), me.get_value()))
+            .collect();
+        entries.iter().map(|(key, crdt_type)| {
+            match index.get(&(*crdt_type, &key.0[..])) {
+                Some(value) => read_crdt_value(*crdt_type, value),
+                None => Err(AntidoteError::EntryNotFound { key: key.0.clone(), crdt_type: *crdt_type }),
+            }
+        }).collect()
+    }
+
+    /// Counts the leaf entries (all entries but maps) of this map and of the maps nested in it, at any depth.
+    /// Walks the nested maps with a work list instead of recursion, so arbitrarily deep maps cannot overflow the stack.
+    pub fn deep_entry_count(&self) -> usize {
+        let mut count = 0;
+        let mut maps = vec!(&self.map_resp);
+        while let Some(map) = maps.pop() {
+            for me in map.get_entries().iter() {
+                match me.get_key().get_field_type() {
+                    CRDT_type::RRMAP | CRDT_type::GMAP => maps.push(me.get_value().get_map()),
+                    _ => count += 1,
+                }
+            }
+        }
+        count
+    }
+
+    /// Computes the updates that turn this map, read from `key` of the given kind, into `desired`: nested entries
+    /// missing in `desired` are removed, the others are created or updated to the desired value (counters are incremented
+    /// by the difference, sets get the missing elements added and the extra ones removed, registers and flags are written
+    /// if they differ, nested maps are diffed recursively). Returns no update if the map already has the desired contents.
+    ///
+    /// The updates are computed against this read, concurrent updates committed after it are not taken into account:
+    /// read and apply the updates in the same interactive transaction, and read again to converge after concurrent changes.
+    /// Fails with `ErrorKind::InvalidInput` if an entry would have to be removed from a grow-only map, for a multi-value
+    /// register with several desired values and for a value not matching the type of its key.
+    pub fn diff_to<K: KeyFor<Map>>(&self, key: &K, kind: MapKind, desired: &DesiredMap) -> Result<Vec<CRDTUpdate>, AntidoteError> {
+        let update = map_diff(key.key(), kind.crdt_type(), Some(self), desired)?;
+        Ok(update.into_iter().collect())
+    }
+
+    /// Returns the keys of all nested entries sorted by type (in the order of `CRDT_type`'s values) and key.
+    /// Antidote returns the entries in no particular order that may differ between reads; the order carries no meaning,
+    /// sorting only makes the result reproducible, e.g. for comparisons in tests.
+    pub fn sorted_keys(&self) -> Vec<MapEntryKey> {
+        let mut keys = self.list_map_keys();
+        keys.sort_by(|a, b| (a.crdt_type.value(), &a.key).cmp(&(b.crdt_type.value(), &b.key)));
+        keys
+    }
+
+    /// Returns all nested entries with their values in a single pass over the map, in the order Antidote returned them,
+    /// e.g. to render a map without knowing its keys. Nested maps become `CRDTValue::Map` values holding their own entries,
+    /// decoded the same way when they are visited. Entries of types without a `CRDTValue` (bounded counters) are skipped.
+    pub fn to_entries(&self) -> Vec<(MapEntryKey, CRDTValue)> {
+        let mut entries = Vec::new();
+        for me in self.map_resp.get_entries().iter() {
+            let crdt_type = me.get_key().get_field_type();
+            if let Some(value) = crdt_value(crdt_type, me.get_value()) {
+                entries.push((MapEntryKey { key: me.get_key().get_key().to_vec(), crdt_type }, value));
+            }
+        }
+        entries
+    }
+
+    /// Returns all nested entries with their values like `to_entries`, sorted like `sorted_keys`.
+    pub fn sorted_entries(&self) -> Vec<(MapEntryKey, CRDTValue)> {
+        let mut entries = self.to_entries();
+        entries.sort_by(|(a, _), (b, _)| (a.crdt_type.value(), &a.key).cmp(&(b.crdt_type.value(), &b.key)));
+        entries
+    }
+}
+
+// Converts a read response of the given type, failing with AntidoteError::UnsupportedCrdtType for types that are not readable.
+fn read_crdt_value(crdt_type: CRDT_type, resp: &ApbReadObjectResp) -> Result<CRDTValue, AntidoteError> {
+    crdt_value(crdt_type, resp).ok_or(AntidoteError::UnsupportedCrdtType(crdt_type))
+}
+
+// Converts a read response of the given type, None for types that are not readable (bounded counters, read with `read_bcounter`).
+fn crdt_value(crdt_type: CRDT_type, resp: &ApbReadObjectResp) -> Option<CRDTValue> {
+    let value = match crdt_type {
+        CRDT_type::COUNTER | CRDT_type::FATCOUNTER => CRDTValue::Counter(resp.get_counter().get_value()),
+        CRDT_type::ORSET | CRDT_type::RWSET => CRDTValue::Set(resp.get_set().get_value().to_vec()),
+        CRDT_type::LWWREG => CRDTValue::Reg(resp.get_reg().get_value().to_vec()),
+        CRDT_type::MVREG => CRDTValue::MVReg(resp.get_mvreg().get_values().to_vec()),
+        CRDT_type::RRMAP | CRDT_type::GMAP => CRDTValue::Map(MapReadResult { map_resp: resp.get_map().clone() }),
+        CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => CRDTValue::Flag(resp.get_flag().get_value()),
+        CRDT_type::BCOUNTER => return None,
+    };
+    Some(value)
+}
+
+/// Derives the CRDT type of a read object from the value Antidote returned, None if it holds no value.
+/// The response only tells the family of the type: counters are reported as `COUNTER`, sets as `ORSET`,
+/// maps as `RRMAP` and flags as `FLAG_EW`.
+pub fn probe_type(resp: &ApbReadObjectResp) -> Option<CRDT_type> {
+    if resp.has_counter() {
+        Some(CRDT_type::COUNTER)
+    } else if resp.has_set() {
+        Some(CRDT_type::ORSET)
+    } else if resp.has_reg() {
+        Some(CRDT_type::LWWREG)
+    } else if resp.has_mvreg() {
+        Some(CRDT_type::MVREG)
+    } else if resp.has_map() {
+        Some(CRDT_type::RRMAP)
+    } else if resp.has_flag() {
+        Some(CRDT_type::FLAG_EW)
+    } else {
+        None
+    }
+}
+
 /// Struct for Map-keys
+/// Keys are ordered by type (in the order of `CRDT_type`'s values) and key, like `MapReadResult::sorted_keys`.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct MapEntryKey {
     pub key: Vec<u8>,
     pub crdt_type: CRDT_type,
 }
+impl Ord for MapEntryKey {
+    fn cmp(&self, other: &MapEntryKey) -> Ordering {
+        (self.crdt_type.value(), &self.key).cmp(&(other.crdt_type.value(), &other.key))
+    }
+}
+impl PartialOrd for MapEntryKey {
+    fn partial_cmp(&self, other: &MapEntryKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 impl fmt::Debug for MapEntryKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "MapEntryKey ({:?}, {:?})", self.key, self.crdt_type)
@@ -322,6 +1315,11 @@ trait UpdateConverter {
     fn convert_to_nested(&self) -> ApbMapNestedUpdate;
 }
 
+/// An update of a single object, built with the functions below (`counter_inc`, `set_add`, ...).
+/// Cloning it allows to apply the same update in several transactions.
+/// An update carries no certification hint: the protocol has none, every update of a transaction is certified
+/// on commit (unless `txn_cert` is disabled on the server), including increments of a counter.
+#[derive(Clone)]
 pub struct CRDTUpdate {
     update: ApbUpdateOperation,
     key: Key,
@@ -354,24 +1352,262 @@ impl UpdateConverter for CRDTUpdate {
     }
 }
 
+impl CRDTUpdate {
+    /// Combines another update of the same key and type into this one, so both are sent as a single operation:
+    /// counter increments are summed, the elements of set adds (or of set removes) are united.
+    /// Returns false and leaves this update unchanged if the updates can not be combined,
+    /// e.g. for different keys, a set add and a set remove, or register writes.
+    pub fn try_merge(&mut self, other: &CRDTUpdate) -> bool {
+        if self.key.0 != other.key.0 || self.crdt_type != other.crdt_type {
+            return false;
+        }
+        let (this, other) = (&mut self.update, &other.update);
+        if this.has_counterop() && other.has_counterop() {
+            match this.get_counterop().get_inc().checked_add(other.get_counterop().get_inc()) {
+                Some(inc) => this.mut_counterop().set_inc(inc),
+                None => return false,
+            }
+            return true;
+        }
+        if this.has_setop() && other.has_setop() && this.get_setop().get_optype() == other.get_setop().get_optype() {
+            let setop = this.mut_setop();
+            let mut adds: HashSet<Vec<u8>> = setop.get_adds().iter().cloned().collect();
+            for elem in other.get_setop().get_adds() {
+                if adds.insert(elem.clone()) {
+                    setop.mut_adds().push(elem.clone());
+                }
+            }
+            let mut rems: HashSet<Vec<u8>> = setop.get_rems().iter().cloned().collect();
+            for elem in other.get_setop().get_rems() {
+                if rems.insert(elem.clone()) {
+                    setop.mut_rems().push(elem.clone());
+                }
+            }
+            return true;
+        }
+        false
+    }
+}
+
+/// Compacts a batch of updates with `CRDTUpdate::try_merge` before sending it, e.g. with `CRDTUpdater::update`.
+/// An update is only merged into the latest preceding update of the same key, so the updates of each object
+/// keep their order: a set add following a remove of the same set is not moved before it.
+pub fn dedupe_and_merge(updates: Vec<CRDTUpdate>) -> Vec<CRDTUpdate> {
+    let mut merged: Vec<CRDTUpdate> = Vec::new();
+    for update in updates {
+        let last = merged.iter_mut().rev().find(|u| u.key.0 == update.key.0 && u.crdt_type == update.crdt_type);
+        if let Some(last) = last {
+            if last.try_merge(&update) {
+                continue;
+            }
+        }
+        merged.push(update);
+    }
+    merged
+}
+
 /// A CRDTUpdater allows to apply updates in the context of a transaction.
 pub trait CRDTUpdater {
-    fn update(&self, tx: &mut dyn Transaction, updates: Vec<CRDTUpdate>) -> Result<(), Error>;
+    fn update(&self, tx: &mut dyn Transaction, updates: Vec<CRDTUpdate>) -> Result<(), AntidoteError>;
 }
 
 impl CRDTUpdater for Bucket {
-    fn update(&self, tx: &mut dyn Transaction, updates: Vec<CRDTUpdate>) -> Result<(), Error> {
-        let mut update_ops: Vec<ApbUpdateOp> = Vec::new();
-        for (_, v) in updates.iter().enumerate() {
-            update_ops.push(v.convert_to_top_level(self.bucket.clone()));
-        } 
-        return tx.update(&update_ops);
+    fn update(&self, tx: &mut dyn Transaction, updates: Vec<CRDTUpdate>) -> Result<(), AntidoteError> {
+        self.update_iter(tx, updates)
     }
 }
 
+impl Bucket {
+    /// Applies updates like `CRDTUpdater::update`, taking them from an iterator
+    /// so lazily generated updates do not need to be collected first.
+    pub fn update_iter<I: IntoIterator<Item = CRDTUpdate>>(&self, tx: &mut dyn Transaction, updates: I) -> Result<(), AntidoteError> {
+        let update_ops: Vec<ApbUpdateOp> = updates.into_iter().map(|u| u.convert_to_top_level(self.bucket.clone())).collect();
+        tx.update(&update_ops)
+    }
+}
+
+/// Convenience operations combining reads and updates in the context of a transaction.
+impl Bucket {
+    /// Removes elements from a set following the observed-remove discipline of ORSETs:
+    /// a remove only takes effect for elements this transaction has observed, so the set is read first
+    /// and only the observed elements are removed.
+    /// Returns the requested elements that were skipped because they are not in the set.
+    pub fn set_remove_observed<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K, elems: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, AntidoteError> {
+        let observed = self.read_set(tx, key)?;
+        let (removes, skipped): (Vec<Vec<u8>>, Vec<Vec<u8>>) = elems.into_iter().partition(|e| observed.contains(e));
+        if !removes.is_empty() {
+            self.update(tx, vec!(set_remove(key, removes)))?;
+        }
+        Ok(skipped)
+    }
+
+    /// Moves an element from the set `from` to the set `to`, e.g. a task id from "pending" to "done": the remove and the add
+    /// are sent as one update message of the transaction and commit together.
+    /// ORSET removes follow the observed-remove discipline, a remove only takes effect for adds the transaction has seen,
+    /// so `from` is read first. If the element is not in it nothing is updated and `false` is returned; a concurrent add
+    /// of the element to `from` survives the move (add-wins).
+    pub fn set_move<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, from: &K, to: &K, elem: Vec<u8>) -> Result<bool, AntidoteError> {
+        if !self.read_set(tx, from)?.contains(&elem) {
+            return Ok(false);
+        }
+        self.update(tx, vec!(set_remove(from, vec!(elem.clone())), set_add(to, vec!(elem))))?;
+        Ok(true)
+    }
+
+    /// Returns the candidates that are not in the set, in the given order, e.g. to dedupe new items against a set
+    /// of seen ones. The set is read once and hashed, so the check takes O(set + candidates).
+    pub fn set_difference<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K, candidates: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, AntidoteError> {
+        let present: HashSet<Vec<u8>> = self.read_set(tx, key)?.into_iter().collect();
+        Ok(candidates.iter().filter(|c| !present.contains(*c)).cloned().collect())
+    }
+
+    /// Removes nested entries from an add-wins map like `set_remove_observed` does for sets:
+    /// the map is read first and only the entries present in it are removed.
+    /// Returns the requested entries that were skipped because they are not in the map.
+    pub fn map_remove_observed<K: KeyFor<Map>>(&self, tx: &mut dyn Transaction, key: &K, removed: Vec<MapEntryKey>) -> Result<Vec<MapEntryKey>, AntidoteError> {
+        let observed = self.read_map(tx, key)?.list_map_keys();
+        let (removes, skipped): (Vec<MapEntryKey>, Vec<MapEntryKey>) = removed.into_iter()
+            .partition(|r| observed.iter().any(|o| o.key == r.key && o.crdt_type == r.crdt_type));
+        if !removes.is_empty() {
+            self.update(tx, vec!(map_remove(key, MapKind::AddWins, removes)?))?;
+        }
+        Ok(skipped)
+    }
+
+    /// Adds elements to a set with one update message per `chunk_size` elements, so bulk imports of many elements
+    /// stay below the maximum message size. Each chunk is a separate update operation of the transaction:
+    /// in an interactive transaction the chunks become visible atomically when the transaction commits, but if a chunk
+    /// fails the earlier ones have already been applied within the transaction, abort it to discard them.
+    /// In a `StaticTransaction` each chunk commits as a transaction of its own: the chunks become visible one by one
+    /// and those before a failed chunk stay committed.
+    pub fn set_add_chunked<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K, elems: Vec<Vec<u8>>, chunk_size: usize) -> Result<(), AntidoteError> {
+        if chunk_size == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "chunk size must be greater than 0").into())
+        }
+        for chunk in elems.chunks(chunk_size) {
+            self.update(tx, vec!(set_add(key, chunk.to_vec())))?;
+        }
+        Ok(())
+    }
+
+    /// Adds elements to a set and returns the set's contents after the update.
+    /// Takes two round trips (Antidote can not combine a read and an update in one message),
+    /// the read runs in the same transaction so it observes the update.
+    pub fn set_add_and_read<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K, elems: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, AntidoteError> {
+        self.update(tx, vec!(set_add(key, elems)))?;
+        self.read_set(tx, key)
+    }
+
+    /// Removes elements from a set and returns the set's contents after the update, see `set_add_and_read`.
+    pub fn set_remove_and_read<K: KeyFor<Set>>(&self, tx: &mut dyn Transaction, key: &K, elems: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, AntidoteError> {
+        self.update(tx, vec!(set_remove(key, elems)))?;
+        self.read_set(tx, key)
+    }
+
+    /// Writes `new` to a register only if it currently holds `expected` (empty for a register never written),
+    /// returns whether the write was applied.
+    /// The comparison uses the snapshot of the transaction, atomicity relies on Antidote's certification:
+    /// if another transaction writes the register concurrently, one of both is aborted on commit (with
+    /// `txn_cert` disabled on the server both commit and the last write wins). Run it in an interactive transaction,
+    /// with a static transaction the read and the write are separate transactions and not atomic at all.
+    pub fn reg_compare_and_set<K: KeyFor<Reg>>(&self, tx: &mut dyn Transaction, key: &K, expected: &[u8], new: Vec<u8>) -> Result<bool, AntidoteError> {
+        if self.read_reg(tx, key)? != expected {
+            return Ok(false);
+        }
+        self.update(tx, vec!(reg_put(key, new)))?;
+        Ok(true)
+    }
+
+    /// Reads a multi-value register and collapses its siblings (concurrently written values): if it holds more than
+    /// one value, `resolver` picks the value to keep, which is written back in the same transaction and returned.
+    /// A register with a single value is returned as is, an empty one as an empty value; neither calls the resolver.
+    /// The repair costs an extra update round trip whenever there are siblings, and only collapses the siblings
+    /// in the transaction's snapshot: writes concurrent to the transaction still add new ones.
+    pub fn read_mv_reg_repair<K: KeyFor<MVReg>, F: FnOnce(&[Vec<u8>]) -> Vec<u8>>(&self, tx: &mut dyn Transaction, key: &K, resolver: F) -> Result<Vec<u8>, AntidoteError> {
+        let mut values = self.read_mv_reg(tx, key)?;
+        if values.len() <= 1 {
+            return Ok(values.pop().unwrap_or_default());
+        }
+        let resolved = resolver(&values);
+        self.update(tx, vec!(mv_reg_put(key, resolved.clone())))?;
+        Ok(resolved)
+    }
+
+    /// Resets several objects of different types with a single update message, e.g. to clean up test fixtures.
+    /// Fails before sending anything if one of the types does not support resets, see `reset`.
+    pub fn reset_many(&self, tx: &mut dyn Transaction, objects: &[(Key, CRDT_type)]) -> Result<(), AntidoteError> {
+        let mut updates = Vec::new();
+        for (key, crdt_type) in objects.iter() {
+            updates.push(reset(key, *crdt_type)?);
+        }
+        self.update(tx, updates)
+    }
+
+    /// Writes initial objects described by their values with a single update message, e.g. for test fixtures
+    /// or the first setup of an application. Values become counters, OR-sets, LWW registers, multi-value registers
+    /// and add-wins maps (nested entries with the type they have in the given map).
+    /// The updates apply on top of existing objects (counters are incremented, elements added), so seed new keys.
+    /// Fails before sending anything for values that cannot be written (flags, multi-value registers with several values).
+    pub fn seed(&self, tx: &mut dyn Transaction, objects: Vec<(Key, CRDTValue)>) -> Result<(), AntidoteError> {
+        let mut updates = Vec::new();
+        for (key, value) in objects.iter() {
+            updates.push(seed_update(key, None, value)?);
+        }
+        self.update(tx, updates)
+    }
+
+    /// Reads an object with the type registered for its key (see `Bucket::with_types`).
+    pub fn read_registered(&self, tx: &mut dyn Transaction, key: &Key) -> Result<CRDTValue, AntidoteError> {
+        let crdt_type = self.registered_type(key)?;
+        let objects = MultiRead::new().add(self, key, crdt_type).read(tx)?;
+        match objects.first() {
+            Some(object) => read_crdt_value(crdt_type, object),
+            None => Err(Error::new(ErrorKind::InvalidData, format!("no value of type {:?} returned for {}", crdt_type, key)).into()),
+        }
+    }
+
+    /// Resets objects like `reset_many`, with the types registered for their keys.
+    pub fn reset_registered(&self, tx: &mut dyn Transaction, keys: &[Key]) -> Result<(), AntidoteError> {
+        let mut objects = Vec::new();
+        for key in keys.iter() {
+            objects.push((Key(key.0.clone()), self.registered_type(key)?));
+        }
+        self.reset_many(tx, &objects)
+    }
+
+    /// Reads a register written with `reg_put_i64`.
+    pub fn read_reg_i64<K: KeyFor<Reg>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<i64, AntidoteError> {
+        let val = self.read_reg(tx, key)?;
+        check_reg_width(key.key(), &val, 8)?;
+        Ok(BigEndian::read_i64(&val))
+    }
+
+    /// Reads a register written with `reg_put_f64`.
+    pub fn read_reg_f64<K: KeyFor<Reg>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<f64, AntidoteError> {
+        let val = self.read_reg(tx, key)?;
+        check_reg_width(key.key(), &val, 8)?;
+        Ok(BigEndian::read_f64(&val))
+    }
+
+    /// Reads a register written with `reg_put_pb` and parses it as a protocol-buffer message of type `M`.
+    /// Fails with `ErrorKind::InvalidData` if the register does not hold a valid message of that type.
+    pub fn read_reg_pb<M: Message, K: KeyFor<Reg>>(&self, tx: &mut dyn Transaction, key: &K) -> Result<M, AntidoteError> {
+        let val = self.read_reg(tx, key)?;
+        M::parse_from_bytes(&val).map_err(|e| Error::new(ErrorKind::InvalidData,
+            format!("register {} holds no valid {}: {}", key.key(), M::descriptor_static().name(), e)).into())
+    }
+}
+
+fn check_reg_width(key: &Key, val: &[u8], width: usize) -> Result<(), AntidoteError> {
+    if val.len() != width {
+        return Err(Error::new(ErrorKind::InvalidData, format!("register {} holds {} bytes, expected {}", key, val.len(), width)).into())
+    }
+    Ok(())
+}
+
 
 // CRDT update operations
-pub fn set_add(key: &Key, elems: Vec<Vec<u8>>) -> CRDTUpdate {
+pub fn set_add<K: KeyFor<Set>>(key: &K, elems: Vec<Vec<u8>>) -> CRDTUpdate {
     let op_type = ApbSetUpdate_SetOpType::ADD;
     let mut apb_set_update = ApbSetUpdate::new();
     apb_set_update.set_adds(RepeatedField::from_vec(elems));
@@ -380,14 +1616,14 @@ pub fn set_add(key: &Key, elems: Vec<Vec<u8>>) -> CRDTUpdate {
     apb_update_operation.set_setop(apb_set_update);
 
     let crdt_update = CRDTUpdate {
-        key: Key(key.0.clone()),
+        key: Key(key.key().0.clone()),
         crdt_type: CRDT_type::ORSET,
         update: apb_update_operation,
     };
     crdt_update
 }
 
-pub fn set_remove(key: &Key, elems: Vec<Vec<u8>>) -> CRDTUpdate {
+pub fn set_remove<K: KeyFor<Set>>(key: &K, elems: Vec<Vec<u8>>) -> CRDTUpdate {
     let op_type = ApbSetUpdate_SetOpType::REMOVE; 
     let mut apb_set_update = ApbSetUpdate::new();
     apb_set_update.set_rems(RepeatedField::from_vec(elems));
@@ -396,56 +1632,103 @@ pub fn set_remove(key: &Key, elems: Vec<Vec<u8>>) -> CRDTUpdate {
     apb_update_operation.set_setop(apb_set_update);
 
     let crdt_update = CRDTUpdate {
-        key: Key(key.0.clone()),
+        key: Key(key.key().0.clone()),
         crdt_type: CRDT_type::ORSET,
         update: apb_update_operation,
     };
     crdt_update
 }
 
-pub fn counter_inc(key: &Key, inc: i64) -> CRDTUpdate {
+pub fn counter_inc<K: KeyFor<Counter>>(key: &K, inc: i64) -> CRDTUpdate {
     let mut apb_counter_update = ApbCounterUpdate::new();
     apb_counter_update.set_inc(inc);
     let mut apb_update_operation = ApbUpdateOperation::new();
     apb_update_operation.set_counterop(apb_counter_update);
 
     let crdt_update = CRDTUpdate {
-        key: Key(key.0.clone()),
+        key: Key(key.key().0.clone()),
         crdt_type: CRDT_type::COUNTER,
         update: apb_update_operation,
     };
     crdt_update
 }
 
-pub fn reg_put(key: &Key, value: Vec<u8>) -> CRDTUpdate {
+/// Decrements a counter, same as `counter_inc` with the negated amount (`i64::MIN` is taken as `-i64::MAX`).
+pub fn counter_dec<K: KeyFor<Counter>>(key: &K, dec: i64) -> CRDTUpdate {
+    counter_inc(key, dec.saturating_neg())
+}
+
+/// Increments a bounded counter (`CRDT_type::BCOUNTER`), which also grants the replica the rights to decrement it by `inc`.
+pub fn bcounter_inc<K: KeyFor<BCounter>>(key: &K, inc: i64) -> CRDTUpdate {
+    let mut apb_update_operation = ApbUpdateOperation::new();
+    apb_update_operation.mut_counterop().set_inc(inc);
+
+    CRDTUpdate {
+        key: Key(key.key().0.clone()),
+        crdt_type: CRDT_type::BCOUNTER,
+        update: apb_update_operation,
+    }
+}
+
+/// Decrements a bounded counter, sent as a negative increment like `counter_dec`.
+/// Antidote only applies the decrement if the replica holds the rights for it, so the counter stays at or above 0;
+/// otherwise the update or the commit fails, e.g. with `AntidoteError::TransactionAborted` and `ErrorCode::NoPermissions`.
+pub fn bcounter_dec<K: KeyFor<BCounter>>(key: &K, dec: i64) -> CRDTUpdate {
+    bcounter_inc(key, dec.saturating_neg())
+}
+
+pub fn reg_put<K: KeyFor<Reg>>(key: &K, value: Vec<u8>) -> CRDTUpdate {
     let mut apb_reg_update = ApbRegUpdate::new();
     apb_reg_update.set_value(value);
     let mut apb_update_operation = ApbUpdateOperation::new();
     apb_update_operation.set_regop(apb_reg_update);
 
     let crdt_update = CRDTUpdate {
-        key: Key(key.0.clone()),
+        key: Key(key.key().0.clone()),
         crdt_type: CRDT_type::LWWREG,
         update: apb_update_operation,
     };
     crdt_update
 }
 
-pub fn mv_reg_put(key: &Key, value: Vec<u8>) -> CRDTUpdate {
+/// Puts a number into a register, encoded as 8 bytes big-endian.
+pub fn reg_put_i64<K: KeyFor<Reg>>(key: &K, value: i64) -> CRDTUpdate {
+    reg_put(key, value.to_be_bytes().to_vec())
+}
+
+/// Puts a protocol-buffer message into a register, serialized in its wire format.
+/// Fails with `ErrorKind::InvalidInput` if the message cannot be serialized, e.g. because required fields are missing.
+pub fn reg_put_pb<M: Message, K: KeyFor<Reg>>(key: &K, message: &M) -> Result<CRDTUpdate, AntidoteError> {
+    let value = message.write_to_bytes().map_err(|e| Error::new(ErrorKind::InvalidInput,
+        format!("cannot serialize {} for register {}: {}", message.descriptor().name(), key.key(), e)))?;
+    Ok(reg_put(key, value))
+}
+
+/// Puts a floating point number into a register, encoded as 8 bytes big-endian.
+pub fn reg_put_f64<K: KeyFor<Reg>>(key: &K, value: f64) -> CRDTUpdate {
+    reg_put(key, value.to_be_bytes().to_vec())
+}
+
+pub fn mv_reg_put<K: KeyFor<MVReg>>(key: &K, value: Vec<u8>) -> CRDTUpdate {
     let mut apb_reg_update = ApbRegUpdate::new();
     apb_reg_update.set_value(value);
     let mut apb_update_operation = ApbUpdateOperation::new();
     apb_update_operation.set_regop(apb_reg_update);
 
     let crdt_update = CRDTUpdate {
-        key: Key(key.0.clone()),
+        key: Key(key.key().0.clone()),
         crdt_type: CRDT_type::MVREG,
         update: apb_update_operation,
     };
     crdt_update
 }
 
-pub fn map_update(key: &Key, updates: Vec<CRDTUpdate>) -> CRDTUpdate {
+/// Updates nested entries of an add-wins map.
+pub fn map_update<K: KeyFor<Map>>(key: &K, updates: Vec<CRDTUpdate>) -> CRDTUpdate {
+    map_update_kind(key, MapKind::AddWins, updates)
+}
+
+pub fn map_update_kind<K: KeyFor<Map>>(key: &K, kind: MapKind, updates: Vec<CRDTUpdate>) -> CRDTUpdate {
     let mut nupdates: Vec<ApbMapNestedUpdate> = Vec::new();
     for (_, v) in updates.iter().enumerate() {
         nupdates.push(v.convert_to_nested());
@@ -456,14 +1739,188 @@ pub fn map_update(key: &Key, updates: Vec<CRDTUpdate>) -> CRDTUpdate {
     apb_update_operation.set_mapop(apb_map_update);
 
     let crdt_update = CRDTUpdate {
-        key: Key(key.0.clone()),
-        crdt_type: CRDT_type::RRMAP,
+        key: Key(key.key().0.clone()),
+        crdt_type: kind.crdt_type(),
         update: apb_update_operation,
     };
     crdt_update
 }
 
+/// Removes nested entries from a map.
+/// Fails for grow-only maps since they do not support removes.
+pub fn map_remove<K: KeyFor<Map>>(key: &K, kind: MapKind, removed: Vec<MapEntryKey>) -> Result<CRDTUpdate, AntidoteError> {
+    if kind == MapKind::GrowOnly {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("cannot remove entries from grow-only map {}", key.key())).into())
+    }
+    let mut removed_keys: Vec<ApbMapKey> = Vec::new();
+    for entry in removed.into_iter() {
+        let mut apb_map_key = ApbMapKey::new();
+        apb_map_key.set_key(entry.key);
+        apb_map_key.set_field_type(entry.crdt_type);
+        removed_keys.push(apb_map_key);
+    }
+    let mut apb_map_update = ApbMapUpdate::new();
+    apb_map_update.set_removedKeys(RepeatedField::from_vec(removed_keys));
+    let mut apb_update_operation = ApbUpdateOperation::new();
+    apb_update_operation.set_mapop(apb_map_update);
+
+    let crdt_update = CRDTUpdate {
+        key: Key(key.key().0.clone()),
+        crdt_type: kind.crdt_type(),
+        update: apb_update_operation,
+    };
+    Ok(crdt_update)
+}
 
+// Update writing a value to an object (or nested map entry) of the given type, of the type derived from the value if None.
+fn seed_update(key: &Key, crdt_type: Option<CRDT_type>, value: &CRDTValue) -> Result<CRDTUpdate, AntidoteError> {
+    let update = match value {
+        CRDTValue::Counter(c) => {
+            let mut update = counter_inc(key, i64::from(*c));
+            update.crdt_type = crdt_type.unwrap_or(CRDT_type::COUNTER);
+            update
+        }
+        CRDTValue::Set(elems) => {
+            let mut update = set_add(key, elems.clone());
+            update.crdt_type = crdt_type.unwrap_or(CRDT_type::ORSET);
+            update
+        }
+        CRDTValue::Reg(v) => reg_put(key, v.clone()),
+        CRDTValue::MVReg(values) if values.len() <= 1 => mv_reg_put(key, values.first().cloned().unwrap_or_default()),
+        CRDTValue::Map(map) => {
+            let mut nested = Vec::new();
+            for me in map.map_resp.get_entries().iter() {
+                let entry_type = me.get_key().get_field_type();
+                let entry_key = Key(me.get_key().get_key().to_vec());
+                nested.push(seed_update(&entry_key, Some(entry_type), &read_crdt_value(entry_type, me.get_value())?)?);
+            }
+            let kind = if crdt_type == Some(CRDT_type::GMAP) { MapKind::GrowOnly } else { MapKind::AddWins };
+            map_update_kind(key, kind, nested)
+        }
+        v => return Err(Error::new(ErrorKind::InvalidInput, format!("cannot seed {} with {:?}", key, v)).into()),
+    };
+    Ok(update)
+}
+
+// Update of a map (or nested map entry) turning `actual` into `desired`, None if nothing changes.
+fn map_diff(key: &Key, crdt_type: CRDT_type, actual: Option<&MapReadResult>, desired: &DesiredMap) -> Result<Option<CRDTUpdate>, AntidoteError> {
+    let mut current: BTreeMap<MapEntryKey, &ApbReadObjectResp> = BTreeMap::new();
+    if let Some(map) = actual {
+        for me in map.map_resp.get_entries().iter() {
+            current.insert(MapEntryKey { key: me.get_key().get_key().to_vec(), crdt_type: me.get_key().get_field_type() }, me.get_value());
+        }
+    }
+    let mut nested = Vec::new();
+    for (entry, value) in desired.iter() {
+        let actual_value = match current.get(entry) {
+            Some(resp) => Some(read_crdt_value(entry.crdt_type, resp)?),
+            None => None,
+        };
+        nested.extend(entry_diff(entry, actual_value, value)?);
+    }
+    let removed: Vec<ApbMapKey> = current.keys().filter(|entry| !desired.contains_key(entry)).map(|entry| {
+        let mut apb_map_key = ApbMapKey::new();
+        apb_map_key.set_key(entry.key.clone());
+        apb_map_key.set_field_type(entry.crdt_type);
+        apb_map_key
+    }).collect();
+    if nested.is_empty() && removed.is_empty() {
+        return Ok(None);
+    }
+    if !removed.is_empty() && crdt_type == CRDT_type::GMAP {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("cannot remove entries from grow-only map {}", key)).into());
+    }
+    let mut apb_map_update = ApbMapUpdate::new();
+    apb_map_update.set_updates(RepeatedField::from_vec(nested.iter().map(|u| u.convert_to_nested()).collect()));
+    apb_map_update.set_removedKeys(RepeatedField::from_vec(removed));
+    let mut apb_update_operation = ApbUpdateOperation::new();
+    apb_update_operation.set_mapop(apb_map_update);
+    Ok(Some(CRDTUpdate { key: key.clone(), crdt_type, update: apb_update_operation }))
+}
 
+// Updates of a nested map entry turning `actual` (None if the entry does not exist) into `desired`.
+fn entry_diff(entry: &MapEntryKey, actual: Option<CRDTValue>, desired: &CRDTValue) -> Result<Vec<CRDTUpdate>, AntidoteError> {
+    let key = Key(entry.key.clone());
+    let mut updates = Vec::new();
+    match (entry.crdt_type, desired) {
+        (CRDT_type::COUNTER, CRDTValue::Counter(d)) | (CRDT_type::FATCOUNTER, CRDTValue::Counter(d)) => {
+            let current = match actual { Some(CRDTValue::Counter(c)) => c, _ => 0 };
+            if *d != current {
+                let mut update = counter_inc(&key, i64::from(*d) - i64::from(current));
+                update.crdt_type = entry.crdt_type;
+                updates.push(update);
+            }
+        }
+        (CRDT_type::ORSET, CRDTValue::Set(d)) | (CRDT_type::RWSET, CRDTValue::Set(d)) => {
+            let current = match actual { Some(CRDTValue::Set(c)) => c, _ => Vec::new() };
+            let present: HashSet<&Vec<u8>> = current.iter().collect();
+            let wanted: HashSet<&Vec<u8>> = d.iter().collect();
+            let adds: Vec<Vec<u8>> = d.iter().filter(|e| !present.contains(e)).cloned().collect();
+            let removes: Vec<Vec<u8>> = current.iter().filter(|e| !wanted.contains(e)).cloned().collect();
+            if !adds.is_empty() {
+                updates.push(set_add(&key, adds));
+            }
+            if !removes.is_empty() {
+                updates.push(set_remove(&key, removes));
+            }
+            for update in updates.iter_mut() {
+                update.crdt_type = entry.crdt_type;
+            }
+        }
+        (CRDT_type::LWWREG, CRDTValue::Reg(d)) => {
+            if !matches!(actual, Some(CRDTValue::Reg(ref c)) if c == d) {
+                updates.push(reg_put(&key, d.clone()));
+            }
+        }
+        (CRDT_type::MVREG, CRDTValue::MVReg(d)) if d.len() <= 1 => {
+            let current = match actual { Some(CRDTValue::MVReg(c)) => c, _ => Vec::new() };
+            if *d != current {
+                updates.push(match d.first() {
+                    Some(value) => mv_reg_put(&key, value.clone()),
+                    None => reset(&key, CRDT_type::MVREG)?,
+                });
+            }
+        }
+        (CRDT_type::FLAG_EW, CRDTValue::Flag(d)) | (CRDT_type::FLAG_DW, CRDTValue::Flag(d)) => {
+            if !matches!(actual, Some(CRDTValue::Flag(c)) if c == *d) {
+                let mut apb_flag_update = ApbFlagUpdate::new();
+                apb_flag_update.set_value(*d);
+                let mut apb_update_operation = ApbUpdateOperation::new();
+                apb_update_operation.set_flagop(apb_flag_update);
+                updates.push(CRDTUpdate { key, crdt_type: entry.crdt_type, update: apb_update_operation });
+            }
+        }
+        (CRDT_type::RRMAP, CRDTValue::Map(d)) | (CRDT_type::GMAP, CRDTValue::Map(d)) => {
+            let mut nested_desired = DesiredMap::new();
+            for me in d.map_resp.get_entries().iter() {
+                let entry_type = me.get_key().get_field_type();
+                nested_desired.insert(MapEntryKey { key: me.get_key().get_key().to_vec(), crdt_type: entry_type },
+                    read_crdt_value(entry_type, me.get_value())?);
+            }
+            let current = match &actual { Some(CRDTValue::Map(c)) => Some(c), _ => None };
+            updates.extend(map_diff(&key, entry.crdt_type, current, &nested_desired)?);
+        }
+        (crdt_type, v) => return Err(Error::new(ErrorKind::InvalidInput, format!("cannot set {} of type {:?} to {:?}", key, crdt_type, v)).into()),
+    }
+    Ok(updates)
+}
 
+/// Resets an object to its initial state.
+/// Only fat counters, sets, multi-value registers, add-wins maps and flags support resets,
+/// fails for the other types (counters, registers, grow-only maps, bounded counters).
+pub fn reset(key: &Key, crdt_type: CRDT_type) -> Result<CRDTUpdate, AntidoteError> {
+    match crdt_type {
+        CRDT_type::FATCOUNTER | CRDT_type::ORSET | CRDT_type::RWSET | CRDT_type::MVREG
+            | CRDT_type::RRMAP | CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => {}
+        _ => return Err(Error::new(ErrorKind::InvalidInput, format!("cannot reset {} of type {:?}", key, crdt_type)).into()),
+    }
+    let mut apb_update_operation = ApbUpdateOperation::new();
+    apb_update_operation.set_resetop(ApbCrdtReset::new());
 
+    let crdt_update = CRDTUpdate {
+        key: Key(key.0.clone()),
+        crdt_type,
+        update: apb_update_operation,
+    };
+    Ok(crdt_update)
+}
@@ -0,0 +1,43 @@
+use std::io::{Error, ErrorKind};
+
+use protobuf::Message;
+use tokio::runtime::Builder;
+
+use antidote_rust_client::antidote_pb::*;
+use antidote_rust_client::coder;
+
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+#[test]
+fn test_async_round_trip() -> Result<(), Error> {
+    let mut msg = ApbStartTransaction::new();
+    msg.set_timestamp("clock".as_bytes().to_vec());
+    let mut expected = Vec::new();
+    msg.encode(&mut expected)?;
+
+    let mut written: Vec<u8> = Vec::new();
+    block_on(coder::encode_msg_async(&msg, 119, &mut written))?;
+    let data = block_on(coder::read_msg_raw_async(&mut &written[..]))?;
+    let mut decoded = ApbStartTransaction::new();
+    decoded.merge_from_bytes(&data[1..])?;
+
+    // asserts
+    assert_eq!(expected, written);
+    assert_eq!(119, data[0]);
+    assert_eq!(msg, decoded);
+    Ok(())
+}
+
+#[test]
+fn test_async_connection_closed() {
+    // header announces 10 bytes, only 2 follow
+    let frame: Vec<u8> = vec![0, 0, 0, 10, 124, 1];
+    let err = block_on(coder::read_msg_raw_async(&mut &frame[..])).err().unwrap();
+
+    // asserts
+    assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    assert!(err.to_string().contains("Connection closed"));
+}
@@ -5,23 +5,19 @@ use std::sync::{Arc};
 use std::thread;
 use std::time::{Instant};
 
-use antidote_rust_client::{Client, Host, new_client};
-use antidote_rust_client::antidote_pb::{CRDT_type};
-use antidote_rust_client::transactions::{MapEntryKey, InteractiveTransaction, 
-    Bucket, Key, CRDTUpdater, CRDTReader, MapReadResultExtractor, 
-    counter_inc, set_add, set_remove, reg_put, map_update
+use antidote_rust_client::{Client, SharedClient};
+use antidote_rust_client::antidote_pb::{ApbGetCounterResp, CRDT_type};
+use antidote_rust_client::error::AntidoteError;
+use antidote_rust_client::transactions::{MapEntryKey, InteractiveTransaction, TransactionMode,
+    Bucket, Key, TypedKey, Counter, BCounter, Set, CRDTUpdater, CRDTReader, MapReadResultExtractor, MapKind, MultiRead, CRDTValue, probe_type,
+    counter_inc, counter_dec, bcounter_inc, bcounter_dec, set_add, set_remove, reg_put, mv_reg_put, reg_put_i64, reg_put_f64, map_update, map_update_kind, map_remove, reset,
+    read_set_across, reg_put_pb
 };
 
 
-/// private setup function: creates a new client to Host{127.0.0.1:8101} and a bucket
+/// private setup function: creates a new client to Host{localhost:8101} and a bucket
 fn setup_interactive() -> Result<(Client, Bucket), Error> {
-    let host = Host {
-        name: String::from("127.0.0.1"),
-        port: 8101,
-    };
-    let mut hosts = Vec::new();
-    hosts.push(host);
-    let client = new_client(hosts)?;
+    let client = Client::local(8101)?;
 
     let timestamp : u128;
     match SystemTime::now().duration_since(UNIX_EPOCH) {
@@ -33,9 +29,7 @@ fn setup_interactive() -> Result<(Client, Bucket), Error> {
 
     let mut bucketname = String::from("bucket");
     bucketname.push_str(timestamp.to_string().as_str()); // always unique bucket name
-    let bucket = Bucket {
-        bucket: bucketname.as_bytes().to_vec(),
-    };
+    let bucket = Bucket::new(bucketname.as_bytes().to_vec());
     Ok((client, bucket))
 }
 
@@ -186,7 +180,7 @@ fn test_map() -> Result<(), Error> {
 #[test]
 fn test_static() -> Result<(), Error> {
     // setup: create client and connection, start interactive transaction
-    let (mut client, bucket) = setup_interactive()?;
+    let (client, bucket) = setup_interactive()?;
 
     let keyname = String::from("keyStatic");
     let key = Key(keyname.as_bytes().to_vec());
@@ -216,13 +210,13 @@ fn test_many_updates() -> Result<(), Error> {
     let mut children: Vec<std::thread::JoinHandle<std::result::Result<(), Error>>> = vec![];
 
     // Thread safe references
-    let arc_c_strong = Arc::new(client);
+    let shared_client = SharedClient::new(client);
     let arc_b_strong = Arc::new(bucket);
     let arc_k_strong = Arc::new(key);
 
     for _thread in 0..NUM_THREADS {
         let builder = thread::Builder::new();
-        let arc_c = arc_c_strong.clone();
+        let arc_c = shared_client.clone();
         let arc_b = arc_b_strong.clone();
         let arc_k = arc_k_strong.clone();
         children.push(builder.spawn(move || {
@@ -245,14 +239,7 @@ fn test_many_updates() -> Result<(), Error> {
         let _ = child.join().expect("Could not join associated thread");
     }
 
-    // reestablish client, bucket and key values
-    let mut client : Client;
-    // need to unwrap the client ref since create_static_transaction needs a mutable reference
-    match Arc::try_unwrap(arc_c_strong) {
-        Ok(c) => client = c,
-        Err(_) => return Err(Error::new(ErrorKind::Other, format!("Could not unwrap client.")))
-    }
-    let mut tx = client.create_static_transaction()?;
+    let mut tx = shared_client.create_static_transaction()?;
     let counter_val = arc_b_strong.read_counter(&mut tx, &arc_k_strong)?;
 
     // assert
@@ -266,7 +253,7 @@ fn test_many_updates() -> Result<(), Error> {
 fn test_many_updates_seq() -> Result<(), Error> {
     let now = Instant::now();
     // setup: create client and connection, start interactive transaction
-    let (mut client, bucket) = setup_interactive()?;
+    let (client, bucket) = setup_interactive()?;
 
     let keyname = String::from("keyManySeq");
     let key = Key(keyname.as_bytes().to_vec());
@@ -294,7 +281,7 @@ fn test_many_updates_seq() -> Result<(), Error> {
 fn test_many_updates_seq_in_trans() -> Result<(), Error> {
     let now = Instant::now();
     // setup: create client and connection, start interactive transaction
-    let (mut client, bucket) = setup_interactive()?;
+    let (client, bucket) = setup_interactive()?;
 
     let keyname = String::from("keyManySeqTrans");
     let key = Key(keyname.as_bytes().to_vec());
@@ -367,4 +354,633 @@ fn test_map_list_map_keys() -> Result<(), Error> {
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_create_dc_invalid_node_name() -> Result<(), Error> {
+    let (client, _) = setup_interactive()?;
+
+    for name in vec!["antidote", "antidote@", "@dc1n1", "antidote@dc1n1@dc1n2", "antidote @dc1n1"] {
+        match client.create_dc(vec!(String::from(name))) {
+            Err(e) => assert_eq!(ErrorKind::InvalidInput, e.kind()),
+            Ok(_) => return Err(Error::new(ErrorKind::Other, format!("malformed node name {} was accepted", name)))
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_map_kinds() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_gmap = Key("keyGMap".as_bytes().to_vec());
+    let key_rrmap = Key("keyRRMap".as_bytes().to_vec());
+    let key_counter = Key("counter".as_bytes().to_vec());
+    let key_reg = Key("reg".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(
+        map_update_kind(&key_gmap, MapKind::GrowOnly, vec!(counter_inc(&key_counter, 3))),
+        map_update(&key_rrmap, vec!(counter_inc(&key_counter, 5), reg_put(&key_reg, "Hello".as_bytes().to_vec()))),
+    ))?;
+    tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    let removed = vec!(MapEntryKey{key: key_reg.0.clone(), crdt_type: CRDT_type::LWWREG});
+    bucket.update(&mut tx, vec!(map_remove(&key_rrmap, MapKind::AddWins, removed)?))?;
+    let gmap_val = bucket.read_map_kind(&mut tx, &key_gmap, MapKind::GrowOnly)?;
+    let rrmap_val = bucket.read_map(&mut tx, &key_rrmap)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(3, gmap_val.counter(&key_counter)?);
+    assert_eq!(5, rrmap_val.counter(&key_counter)?);
+    assert!(rrmap_val.reg(&key_reg).is_err());
+
+    // removes are rejected client-side for grow-only maps
+    let removed = vec!(MapEntryKey{key: key_counter.0.clone(), crdt_type: CRDT_type::COUNTER});
+    assert!(map_remove(&key_gmap, MapKind::GrowOnly, removed).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_multi_read() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+    let other_bucket = Bucket::new([bucket.bucket.clone(), "other".as_bytes().to_vec()].concat());
+
+    let key_counter = Key("keyCounter".as_bytes().to_vec());
+    let key_set = Key("keySet".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(counter_inc(&key_counter, 7)))?;
+    other_bucket.update(&mut tx, vec!(set_add(&key_set, vec!("A".as_bytes().to_vec()))))?;
+
+    let objects = MultiRead::new()
+        .add(&bucket, &key_counter, CRDT_type::COUNTER)
+        .add(&other_bucket, &key_set, CRDT_type::ORSET)
+        .read(&mut tx)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(2, objects.len());
+    assert_eq!(7, objects[0].get_counter().get_value());
+    assert_eq!(vec!("A".as_bytes().to_vec()), objects[1].get_set().get_value().to_vec());
+    Ok(())
+}
+
+#[test]
+fn test_read_set_sorted() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key = Key("keySetSorted".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let elems = vec!("c".as_bytes().to_vec(), "a".as_bytes().to_vec(), "b".as_bytes().to_vec());
+    bucket.update(&mut tx, vec!(set_add(&key, elems)))?;
+    let set_val = bucket.read_set_sorted(&mut tx, &key)?;
+    tx.commit()?;
+
+    // assert
+    assert_eq!(vec!("a".as_bytes().to_vec(), "b".as_bytes().to_vec(), "c".as_bytes().to_vec()), set_val);
+    Ok(())
+}
+
+#[test]
+fn test_transaction_builder() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key = Key("keyBuilder".as_bytes().to_vec());
+
+    let mut tx = client.transaction_builder()
+        .mode(TransactionMode::WriteOnly)
+        .start()?;
+    bucket.update(&mut tx, vec!(counter_inc(&key, 2)))?;
+    tx.commit()?;
+
+    let mut tx = client.transaction_builder()
+        .mode(TransactionMode::ReadOnly)
+        .start()?;
+    let counter_val = bucket.read_counter(&mut tx, &key)?;
+    tx.commit()?;
+
+    // assert
+    assert_eq!(2, counter_val);
+    Ok(())
+}
+
+#[test]
+fn test_counter_dec() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key = Key("keyCounterDec".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(counter_inc(&key, 10)))?;
+    bucket.update(&mut tx, vec!(counter_dec(&key, 4)))?;
+    tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    let counter_val = bucket.read_counter(&mut tx, &key)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(6, counter_val);
+    Ok(())
+}
+
+#[test]
+fn test_bcounter_dec_below_bound() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key: TypedKey<BCounter> = TypedKey::new("keyBCounterBound".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(bcounter_inc(&key, 5)))?;
+    tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    let before = bucket.read_bcounter(&mut tx, &key)?;
+    tx.commit()?;
+
+    // Antidote rejects the decrement either with the update or when committing
+    let mut tx = client.start_transaction()?;
+    let rejected = match bucket.update(&mut tx, vec!(bcounter_dec(&key, before + 1))) {
+        Ok(()) => tx.commit().err(),
+        Err(e) => Some(e),
+    };
+
+    let mut tx = client.start_transaction()?;
+    let after = bucket.read_bcounter(&mut tx, &key)?;
+    tx.commit()?;
+
+    // asserts
+    let err = rejected.expect("decrement below the bound was accepted");
+    assert!(matches!(err, AntidoteError::OperationFailed { .. } | AntidoteError::TransactionAborted { .. }), "{}", err);
+    assert_eq!(before, after);
+    assert!(after >= 0);
+    Ok(())
+}
+
+#[test]
+fn test_set_remove_observed() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key = Key("keySetObserved".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(set_add(&key, vec!("A".as_bytes().to_vec(), "B".as_bytes().to_vec()))))?;
+    tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    let skipped = bucket.set_remove_observed(&mut tx, &key, vec!("A".as_bytes().to_vec(), "C".as_bytes().to_vec()))?;
+    let set_val = bucket.read_set(&mut tx, &key)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(vec!("C".as_bytes().to_vec()), skipped);
+    assert_eq!(vec!("B".as_bytes().to_vec()), set_val);
+    Ok(())
+}
+
+#[test]
+fn test_set_move() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let pending = Key("keySetPending".as_bytes().to_vec());
+    let done = Key("keySetDone".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(set_add(&pending, vec!("task1".as_bytes().to_vec(), "task2".as_bytes().to_vec()))))?;
+    tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    let moved = bucket.set_move(&mut tx, &pending, &done, "task1".as_bytes().to_vec())?;
+    let missing = bucket.set_move(&mut tx, &pending, &done, "task3".as_bytes().to_vec())?;
+    tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    let pending_val = bucket.read_set(&mut tx, &pending)?;
+    let done_val = bucket.read_set(&mut tx, &done)?;
+    tx.commit()?;
+
+    // asserts
+    assert!(moved);
+    assert!(!missing);
+    assert_eq!(vec!("task2".as_bytes().to_vec()), pending_val);
+    assert_eq!(vec!("task1".as_bytes().to_vec()), done_val);
+    Ok(())
+}
+
+#[test]
+fn test_set_difference() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key = Key("keySetDifference".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(set_add(&key, vec!("A".as_bytes().to_vec(), "B".as_bytes().to_vec()))))?;
+    let candidates = vec!("C".as_bytes().to_vec(), "A".as_bytes().to_vec(), "D".as_bytes().to_vec());
+    let missing = bucket.set_difference(&mut tx, &key, &candidates)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(vec!("C".as_bytes().to_vec(), "D".as_bytes().to_vec()), missing);
+    Ok(())
+}
+
+#[test]
+fn test_reg_numbers() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_i64 = Key("keyRegI64".as_bytes().to_vec());
+    let key_f64 = Key("keyRegF64".as_bytes().to_vec());
+    let key_str = Key("keyRegStr".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(
+        reg_put_i64(&key_i64, -42),
+        reg_put_f64(&key_f64, 1.5),
+        reg_put(&key_str, "short".as_bytes().to_vec()),
+    ))?;
+    let i64_val = bucket.read_reg_i64(&mut tx, &key_i64)?;
+    let f64_val = bucket.read_reg_f64(&mut tx, &key_f64)?;
+    let str_as_i64 = bucket.read_reg_i64(&mut tx, &key_str);
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(-42, i64_val);
+    assert_eq!(1.5, f64_val);
+    assert_eq!(ErrorKind::InvalidData, str_as_i64.err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_reg_pb() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_pb = Key("keyRegPb".as_bytes().to_vec());
+    let key_str = Key("keyRegPbStr".as_bytes().to_vec());
+    let mut message = ApbGetCounterResp::new();
+    message.set_value(42);
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(
+        reg_put_pb(&key_pb, &message)?,
+        reg_put(&key_str, "no message".as_bytes().to_vec()),
+    ))?;
+    let pb_val: ApbGetCounterResp = bucket.read_reg_pb(&mut tx, &key_pb)?;
+    let str_as_pb = bucket.read_reg_pb::<ApbGetCounterResp, _>(&mut tx, &key_str);
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(42, pb_val.get_value());
+    assert_eq!(ErrorKind::InvalidData, str_as_pb.err().unwrap().kind());
+    // the required value is missing
+    assert_eq!(ErrorKind::InvalidInput, reg_put_pb(&key_pb, &ApbGetCounterResp::new()).err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_typed_keys() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_counter: TypedKey<Counter> = TypedKey::new("keyTypedCounter".as_bytes().to_vec());
+    let key_set: TypedKey<Set> = TypedKey::new("keyTypedSet".as_bytes().to_vec());
+
+    // bucket.read_counter(&mut tx, &key_set) would not compile
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(counter_inc(&key_counter, 3), set_add(&key_set, vec!("A".as_bytes().to_vec()))))?;
+    let counter_val = bucket.read_counter(&mut tx, &key_counter)?;
+    let set_val = bucket.read_set(&mut tx, &key_set)?;
+    // untyped keys still work for any type
+    let untyped_val = bucket.read_counter(&mut tx, &key_counter.key)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(3, counter_val);
+    assert_eq!(3, untyped_val);
+    assert_eq!(vec!("A".as_bytes().to_vec()), set_val);
+    Ok(())
+}
+
+#[test]
+fn test_map_entries_of_type() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_map = Key("keyCounterDict".as_bytes().to_vec());
+    let key_a = Key("a".as_bytes().to_vec());
+    let key_b = Key("b".as_bytes().to_vec());
+    let key_reg = Key("reg".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(map_update(&key_map, vec!(
+        counter_inc(&key_a, 1),
+        counter_inc(&key_b, 2),
+        reg_put(&key_reg, "Hello".as_bytes().to_vec()),
+    ))))?;
+    let map_val = bucket.read_map(&mut tx, &key_map)?;
+    tx.commit()?;
+
+    let mut counters: Vec<(Vec<u8>, i32)> = Vec::new();
+    for (key, value) in map_val.entries_of_type(CRDT_type::COUNTER) {
+        match value {
+            CRDTValue::Counter(c) => counters.push((key, c)),
+            _ => panic!("expected a counter"),
+        }
+    }
+    counters.sort();
+
+    // asserts
+    assert_eq!(vec!((key_a.0, 1), (key_b.0, 2)), counters);
+    assert_eq!(1, map_val.entries_of_type(CRDT_type::LWWREG).len());
+    assert!(map_val.entries_of_type(CRDT_type::ORSET).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_map_sorted_entries() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_map = Key("keySortedMap".as_bytes().to_vec());
+    let key_a = Key("a".as_bytes().to_vec());
+    let key_b = Key("b".as_bytes().to_vec());
+    let key_c = Key("c".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(map_update(&key_map, vec!(
+        reg_put(&key_a, "Hello".as_bytes().to_vec()),
+        set_add(&key_c, vec!("A".as_bytes().to_vec())),
+        counter_inc(&key_b, 2),
+        counter_inc(&key_a, 1),
+    ))))?;
+    let map_val = bucket.read_map(&mut tx, &key_map)?;
+    tx.commit()?;
+
+    let keys: Vec<(CRDT_type, Vec<u8>)> = map_val.sorted_keys().into_iter().map(|k| (k.crdt_type, k.key)).collect();
+    let entries = map_val.sorted_entries();
+
+    // asserts
+    assert_eq!(vec!(
+        (CRDT_type::COUNTER, key_a.0.clone()),
+        (CRDT_type::COUNTER, key_b.0.clone()),
+        (CRDT_type::ORSET, key_c.0.clone()),
+        (CRDT_type::LWWREG, key_a.0.clone()),
+    ), keys);
+    assert_eq!(4, entries.len());
+    match &entries[0] {
+        (k, CRDTValue::Counter(c)) => assert_eq!((&key_a.0, 1), (&k.key, *c)),
+        (k, v) => panic!("expected counter a, got {:?} {:?}", k, v),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_map_get_many() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_map = Key("keyGetManyMap".as_bytes().to_vec());
+    let key_counter = Key("counter".as_bytes().to_vec());
+    let key_reg = Key("reg".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(map_update(&key_map, vec!(
+        counter_inc(&key_counter, 13),
+        reg_put(&key_reg, "Hello".as_bytes().to_vec()),
+    ))))?;
+    let map_val = bucket.read_map(&mut tx, &key_map)?;
+    tx.commit()?;
+
+    let values = map_val.get_many(&[
+        (key_reg.clone(), CRDT_type::LWWREG),
+        (key_counter.clone(), CRDT_type::COUNTER),
+        (key_counter.clone(), CRDT_type::ORSET),
+    ]);
+
+    // asserts
+    assert_eq!(3, values.len());
+    match &values[0] {
+        Ok(CRDTValue::Reg(r)) => assert_eq!(&"Hello".as_bytes().to_vec(), r),
+        v => panic!("expected the register, got {:?}", v),
+    }
+    match &values[1] {
+        Ok(CRDTValue::Counter(c)) => assert_eq!(13, *c),
+        v => panic!("expected the counter, got {:?}", v),
+    }
+    // the key exists, but not with that type
+    assert!(values[2].is_err());
+    Ok(())
+}
+
+#[test]
+fn test_read_set_across() -> Result<(), Error> {
+    let (client, _) = setup_interactive()?;
+    let suffix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let shards: Vec<Bucket> = (0..2).map(|i| Bucket::new(format!("shard{}-{}", i, suffix).into_bytes())).collect();
+    let key = Key("members".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    shards[0].update(&mut tx, vec!(set_add(&key, vec!("B".as_bytes().to_vec(), "A".as_bytes().to_vec()))))?;
+    shards[1].update(&mut tx, vec!(set_add(&key, vec!("C".as_bytes().to_vec(), "A".as_bytes().to_vec()))))?;
+    let union = read_set_across(&mut tx, &shards, &key)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(vec!("A".as_bytes().to_vec(), "B".as_bytes().to_vec(), "C".as_bytes().to_vec()), union);
+    Ok(())
+}
+
+#[test]
+fn test_set_add_wins() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+    let suffix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let key = Key(format!("keyAddWins{}", suffix).into_bytes());
+    let elem = "A".as_bytes().to_vec();
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(set_add(&key, vec!(elem.clone()))))?;
+    tx.commit()?;
+
+    // concurrent add and remove, the remove commits last but does not see the add
+    let mut remove_tx = client.start_transaction()?;
+    let mut add_tx = client.start_transaction()?;
+    bucket.update(&mut remove_tx, vec!(set_remove(&key, vec!(elem.clone()))))?;
+    bucket.update(&mut add_tx, vec!(set_add(&key, vec!(elem.clone()))))?;
+    add_tx.commit()?;
+    remove_tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    let after_concurrent = bucket.read_set(&mut tx, &key)?;
+    // a remove that sees all adds removes the element
+    bucket.update(&mut tx, vec!(set_remove(&key, vec!(elem.clone()))))?;
+    tx.commit()?;
+    let mut tx = client.start_transaction()?;
+    let after_observed = bucket.read_set(&mut tx, &key)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(vec!(elem), after_concurrent);
+    assert!(after_observed.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_reg_compare_and_set() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_reg = Key("keyVersion".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let initial = bucket.reg_compare_and_set(&mut tx, &key_reg, &[], "v1".as_bytes().to_vec())?;
+    let stale = bucket.reg_compare_and_set(&mut tx, &key_reg, "v0".as_bytes(), "v2".as_bytes().to_vec())?;
+    let current = bucket.reg_compare_and_set(&mut tx, &key_reg, "v1".as_bytes(), "v2".as_bytes().to_vec())?;
+    let val = bucket.read_reg(&mut tx, &key_reg)?;
+    tx.commit()?;
+
+    // asserts
+    assert!(initial);
+    assert!(!stale);
+    assert!(current);
+    assert_eq!("v2".as_bytes().to_vec(), val);
+    Ok(())
+}
+
+#[test]
+fn test_set_add_and_read() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_set = Key("keySetAddAndRead".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let mut added = bucket.set_add_and_read(&mut tx, &key_set, vec!("A".as_bytes().to_vec(), "B".as_bytes().to_vec()))?;
+    let removed = bucket.set_remove_and_read(&mut tx, &key_set, vec!("A".as_bytes().to_vec()))?;
+    tx.commit()?;
+
+    // asserts
+    added.sort();
+    assert_eq!(vec!("A".as_bytes().to_vec(), "B".as_bytes().to_vec()), added);
+    assert_eq!(vec!("B".as_bytes().to_vec()), removed);
+    Ok(())
+}
+
+#[test]
+fn test_reset_many() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_set_a = Key("keyResetSetA".as_bytes().to_vec());
+    let key_set_b = Key("keyResetSetB".as_bytes().to_vec());
+    let key_map = Key("keyResetMap".as_bytes().to_vec());
+    let key_counter = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(
+        set_add(&key_set_a, vec!("A".as_bytes().to_vec())),
+        set_add(&key_set_b, vec!("B".as_bytes().to_vec())),
+        map_update(&key_map, vec!(counter_inc(&key_counter, 1))),
+    ))?;
+    tx.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    bucket.reset_many(&mut tx, &[
+        (key_set_a.clone(), CRDT_type::ORSET),
+        (key_set_b.clone(), CRDT_type::ORSET),
+        (key_map.clone(), CRDT_type::RRMAP),
+    ])?;
+    let set_a_val = bucket.read_set(&mut tx, &key_set_a)?;
+    let set_b_val = bucket.read_set(&mut tx, &key_set_b)?;
+    let map_val = bucket.read_map(&mut tx, &key_map)?;
+    tx.commit()?;
+
+    // asserts
+    assert!(set_a_val.is_empty());
+    assert!(set_b_val.is_empty());
+    assert!(map_val.list_map_keys().is_empty());
+    assert_eq!(ErrorKind::InvalidInput, reset(&key_counter, CRDT_type::LWWREG).err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_map_remove_observed() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_map = Key("keyMapRemoveObserved".as_bytes().to_vec());
+    let key_counter = Key("counter".as_bytes().to_vec());
+    let key_reg = Key("reg".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(map_update(&key_map, vec!(counter_inc(&key_counter, 1)))))?;
+    let skipped = bucket.map_remove_observed(&mut tx, &key_map, vec!(
+        MapEntryKey{key: key_counter.0.clone(), crdt_type: CRDT_type::COUNTER},
+        MapEntryKey{key: key_reg.0.clone(), crdt_type: CRDT_type::LWWREG},
+    ))?;
+    let map_val = bucket.read_map(&mut tx, &key_map)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(1, skipped.len());
+    assert_eq!(key_reg.0, skipped[0].key);
+    assert!(map_val.list_map_keys().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_static_read_your_write() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key = Key("keyStaticReadYourWrite".as_bytes().to_vec());
+
+    let mut tx = client.create_static_transaction()?;
+    let read = MultiRead::new().add(&bucket, &key, CRDT_type::COUNTER);
+    let resp = tx.static_read_your_write(&bucket, vec!(counter_inc(&key, 7)), &read)?;
+
+    // asserts
+    assert_eq!(1, resp.len());
+    assert_eq!(7, resp[0].get_counter().get_value());
+    Ok(())
+}
+
+#[test]
+fn test_register_type_mismatch() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_reg = Key("keyLWWReg".as_bytes().to_vec());
+    let key_mv_reg = Key("keyMVReg".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(reg_put(&key_reg, "A".as_bytes().to_vec()), mv_reg_put(&key_mv_reg, "B".as_bytes().to_vec())))?;
+    tx.commit()?;
+
+    // asserts: cross-type reads of the untyped keys fail
+    let mut tx = client.start_transaction()?;
+    assert!(bucket.read_mv_reg(&mut tx, &key_reg).is_err());
+    let _ = tx.abort();
+    let mut tx = client.start_transaction()?;
+    assert!(bucket.read_reg(&mut tx, &key_mv_reg).is_err());
+    let _ = tx.abort();
+    Ok(())
+}
+
+#[test]
+fn test_multi_read_typed() -> Result<(), Error> {
+    let (client, bucket) = setup_interactive()?;
+
+    let key_counter = Key("keyTypedCounter".as_bytes().to_vec());
+    let key_set = Key("keyTypedSet".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(counter_inc(&key_counter, 2), set_add(&key_set, vec!("A".as_bytes().to_vec()))))?;
+    let read = MultiRead::new()
+        .add(&bucket, &key_counter, CRDT_type::COUNTER)
+        .add(&bucket, &key_set, CRDT_type::ORSET);
+    let objects = read.read(&mut tx)?;
+    let typed = read.read_typed(&mut tx)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(Some(CRDT_type::COUNTER), probe_type(&objects[0]));
+    assert_eq!(Some(CRDT_type::ORSET), probe_type(&objects[1]));
+    match &typed[..] {
+        [(CRDT_type::COUNTER, CRDTValue::Counter(2)), (CRDT_type::ORSET, CRDTValue::Set(elems))] => assert_eq!(&vec!("A".as_bytes().to_vec()), elems),
+        _ => panic!("unexpected values {:?}", typed),
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::io::{Cursor, Error, ErrorKind, Read, Write};
+
+use protobuf::Message;
+
+use antidote_rust_client::antidote_pb::*;
+use antidote_rust_client::coder;
+use antidote_rust_client::error::{AntidoteError, DesyncReason};
+
+
+// Transport that hands out its input one byte per read and records everything written.
+struct MockTransport {
+    input: Vec<u8>,
+    pos: usize,
+    output: Vec<u8>,
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.input.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.input[self.pos];
+        self.pos += 1;
+        Ok(1)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_encode_frame() -> Result<(), Error> {
+    let mut start = ApbStartTransaction::new();
+    start.set_properties(ApbTxnProperties::new());
+    let mut buf: Vec<u8> = Vec::new();
+    start.encode(&mut buf)?;
+
+    // 4 byte size (message code + message), message code, message
+    let body = start.write_to_bytes().unwrap();
+    assert_eq!((body.len() + 1) as u32, u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]));
+    assert_eq!(119, buf[4]);
+    assert_eq!(body, buf[5..].to_vec());
+    Ok(())
+}
+
+#[test]
+fn test_decode_from_buffer() -> Result<(), Error> {
+    let mut resp = ApbCommitResp::new();
+    resp.set_success(true);
+    resp.set_commit_time("clock".as_bytes().to_vec());
+    let body = resp.write_to_bytes().unwrap();
+    let mut buf: Vec<u8> = Vec::new();
+    buf.extend_from_slice(&((body.len() + 1) as u32).to_be_bytes());
+    buf.push(127);
+    buf.extend_from_slice(&body);
+
+    let decoded = coder::decode_commit_resp(&mut Cursor::new(buf.clone()))?;
+    assert!(decoded.get_success());
+    assert_eq!("clock".as_bytes(), decoded.get_commit_time());
+
+    // wrong message code and truncated frame
+    assert!(coder::decode_operation_resp(&mut Cursor::new(buf.clone())).is_err());
+    let err = coder::decode_commit_resp(&mut Cursor::new(buf[..buf.len() - 1].to_vec())).err().unwrap();
+    assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    Ok(())
+}
+
+#[test]
+fn test_mock_transport() -> Result<(), Error> {
+    let mut resp = ApbOperationResp::new();
+    resp.set_success(true);
+    let body = resp.write_to_bytes().unwrap();
+    let mut input: Vec<u8> = Vec::new();
+    input.extend_from_slice(&((body.len() + 1) as u32).to_be_bytes());
+    input.push(111);
+    input.extend_from_slice(&body);
+    let mut transport = MockTransport { input, pos: 0, output: Vec::new() };
+
+    let mut msg = ApbAbortTransaction::new();
+    msg.set_transaction_descriptor("txid".as_bytes().to_vec());
+    msg.encode(&mut transport)?;
+    let decoded = coder::decode_operation_resp(&mut transport)?;
+    // the same through trait objects, e.g. a boxed transport
+    msg.encode(&mut transport as &mut dyn Write)?;
+    transport.pos = 0;
+    let decoded_dyn = coder::decode_operation_resp(&mut transport as &mut dyn Read)?;
+
+    // asserts
+    assert!(decoded.get_success());
+    assert_eq!(decoded, decoded_dyn);
+    assert_eq!(120, transport.output[4]);
+    let frame = transport.output.len() / 2;
+    assert_eq!(msg.write_to_bytes().unwrap(), transport.output[5..frame].to_vec());
+    assert_eq!(transport.output[..frame], transport.output[frame..]);
+    Ok(())
+}
+
+#[test]
+fn test_http_response_detected() {
+    let response = "HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\n\r\n".as_bytes().to_vec();
+    let err = coder::decode_start_transaction_resp(&mut Cursor::new(response)).err().unwrap();
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("HTTP endpoint"));
+}
+
+#[test]
+fn test_empty_frame() {
+    let err = coder::decode_operation_resp(&mut Cursor::new(vec![0u8; 4])).err().unwrap();
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("empty frame"));
+    assert!(matches!(AntidoteError::downcast(&err), Some(AntidoteError::StreamDesync(DesyncReason::EmptyFrame))));
+}
+
+#[test]
+fn test_malformed_message() {
+    // message code 111 followed by a truncated varint
+    let err = coder::decode_operation_resp(&mut Cursor::new(vec![0, 0, 0, 3, 111, 0x08, 0xff])).err().unwrap();
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("Cannot decode ApbOperationResp (message code 111)"));
+}
+
+#[test]
+fn test_encode_uninitialized_message() {
+    // the transaction descriptor is required
+    let msg = ApbReadObjects::new();
+    let mut buf = Vec::new();
+    let err = msg.encode(&mut buf).err().unwrap();
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("ApbReadObjects (message code 116)"));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_encode_oversized_message() {
+    // two elements of 2.5 GiB: the frame size would not fit the 4 byte header
+    // (zeroed allocations, the pages are only mapped by the OS once touched)
+    let element = 5usize << 29;
+    let mut add = ApbSetUpdate::new();
+    add.set_optype(ApbSetUpdate_SetOpType::ADD);
+    add.set_adds(protobuf::RepeatedField::from_vec(vec!(vec![0u8; element], vec![0u8; element])));
+    let mut op = ApbUpdateOp::new();
+    op.mut_boundobject().set_key("set".as_bytes().to_vec());
+    op.mut_boundobject().set_field_type(CRDT_type::ORSET);
+    op.mut_boundobject().set_bucket("bucket".as_bytes().to_vec());
+    op.mut_operation().set_setop(add);
+    let mut msg = ApbUpdateObjects::new();
+    msg.mut_updates().push(op);
+    msg.set_transaction_descriptor("descriptor".as_bytes().to_vec());
+    let mut buf = Vec::new();
+    let err = msg.encode(&mut buf).err().unwrap();
+
+    // asserts: rejected before anything was written, in debug builds without protobuf's size computation panicking
+    assert_eq!(ErrorKind::InvalidInput, err.kind());
+    assert!(err.to_string().contains("ApbUpdateObjects (message code 118)"), "{}", err);
+    assert!(buf.is_empty());
+}
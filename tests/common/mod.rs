@@ -0,0 +1,373 @@
+// Fake Antidote server for tests that need to control the connection (no docker setup required).
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use byteorder::{BigEndian, ByteOrder};
+use protobuf::Message;
+
+use antidote_rust_client::antidote_pb::*;
+use antidote_rust_client::{Client, ClientBuilder, Host};
+
+
+/// Value of the counters and bounded counters read from a `FakeAntidote`.
+pub const COUNTER_VALUE: i32 = 5;
+
+/// Connection descriptor of a DC with a single node, in Erlang's external term format:
+/// `{descriptor, {'antidote@127.0.0.1', {1700, 1, 2}}, 64, [[{{127,0,0,1}, 8086}]], [[{{127,0,0,1}, 8085}]]}`
+pub fn descriptor() -> Vec<u8> {
+    let mut d = vec![131, 104, 5, 100, 0, 10];
+    d.extend_from_slice(b"descriptor");
+    d.extend_from_slice(&[104, 2, 100, 0, 18]);
+    d.extend_from_slice(b"antidote@127.0.0.1");
+    d.extend_from_slice(&[104, 3, 98, 0, 0, 6, 164, 97, 1, 97, 2, 97, 64]);
+    for port in [8086u16, 8085].iter() {
+        d.extend_from_slice(&[108, 0, 0, 0, 1, 108, 0, 0, 0, 1, 104, 2, 104, 4, 97, 127, 97, 0, 97, 0, 97, 1, 98, 0, 0]);
+        d.extend_from_slice(&port.to_be_bytes());
+        d.extend_from_slice(&[106, 106]);
+    }
+    d
+}
+
+/// Transaction descriptor handed out on start, in Erlang's external term format:
+/// `{tx_id, TX_START_TIME, <0.123.0>}` with the coordinating process on node `antidote@127.0.0.1`
+pub const TX_START_TIME: u64 = 1_700_000_000_000_000;
+
+pub fn transaction_descriptor() -> Vec<u8> {
+    let mut d = vec![131, 104, 3, 100, 0, 5];
+    d.extend_from_slice(b"tx_id");
+    d.extend_from_slice(&[110, 7, 0]);
+    d.extend_from_slice(&TX_START_TIME.to_le_bytes()[..7]);
+    d.extend_from_slice(&[88, 100, 0, 18]);
+    d.extend_from_slice(b"antidote@127.0.0.1");
+    d.extend_from_slice(&[0, 0, 0, 123, 0, 0, 0, 0, 0, 0, 0, 1]);
+    d
+}
+
+/// Deterministic logical clock standing in for Antidote's vector clocks: every commit advances the time by one
+/// and returns it as commit time (`commit_time`), every transaction start records the snapshot time it asks for.
+#[derive(Default)]
+pub struct Clock {
+    time: AtomicU64,
+    snapshots: Mutex<Vec<Option<u64>>>,
+}
+
+impl Clock {
+    /// Snapshot times requested by the transactions started so far (including static ones), None for no timestamp.
+    pub fn snapshots(&self) -> Vec<Option<u64>> {
+        self.snapshots.lock().unwrap().clone()
+    }
+
+    fn commit(&self) -> Vec<u8> {
+        commit_time(self.time.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn now(&self) -> Vec<u8> {
+        commit_time(self.time.load(Ordering::SeqCst))
+    }
+
+    fn start(&self, properties: &ApbStartTransaction) {
+        let snapshot = match properties.has_timestamp() {
+            true => Some(BigEndian::read_u64(properties.get_timestamp())),
+            false => None,
+        };
+        self.snapshots.lock().unwrap().push(snapshot);
+    }
+}
+
+/// Commit time of the given logical time of a `Clock`.
+pub fn commit_time(time: u64) -> Vec<u8> {
+    time.to_be_bytes().to_vec()
+}
+
+/// Answers start/commit/abort/update requests with success (starts with `transaction_descriptor()`,
+/// commits with the time of the fake's `Clock`), except updates with an operation that does not fit
+/// the type of its object, read requests with empty objects
+/// (counters and bounded counters with `COUNTER_VALUE`)
+/// and connection descriptor requests with `descriptor()`.
+/// Counts the accepted connections and the served requests, and closes a connection after `close_after` requests on it.
+/// With `commit_error` set, commits are answered with that error code.
+pub struct FakeAntidote {
+    pub port: u16,
+    pub accepted: Arc<AtomicUsize>,
+    pub requests: Arc<AtomicUsize>,
+    pub clock: Arc<Clock>,
+}
+
+impl FakeAntidote {
+    pub fn start(close_after: Option<usize>) -> FakeAntidote {
+        FakeAntidote::start_with(close_after, None)
+    }
+
+    pub fn start_with(close_after: Option<usize>, commit_error: Option<u32>) -> FakeAntidote {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let accepted_ref = accepted.clone();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_ref = requests.clone();
+        let clock = Arc::new(Clock::default());
+        let clock_ref = clock.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+                accepted_ref.fetch_add(1, Ordering::SeqCst);
+                let requests = requests_ref.clone();
+                let clock = clock_ref.clone();
+                thread::spawn(move || serve(stream, close_after, commit_error, requests, clock));
+            }
+        });
+        FakeAntidote { port, accepted, requests, clock }
+    }
+
+    /// Client with a single pooled connection to this server.
+    pub fn client(&self) -> Client {
+        ClientBuilder::new()
+            .host(Host::new("127.0.0.1", self.port))
+            .max_pool_size(1)
+            .build()
+            .unwrap()
+    }
+
+    pub fn accepted(&self) -> usize {
+        self.accepted.load(Ordering::SeqCst)
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+fn serve(mut stream: TcpStream, close_after: Option<usize>, commit_error: Option<u32>, requests: Arc<AtomicUsize>, clock: Arc<Clock>) {
+    let mut served = 0;
+    loop {
+        if let Some(n) = close_after {
+            if served == n {
+                return;
+            }
+        }
+        let mut size_b = [0u8; 4];
+        if stream.read_exact(&mut size_b).is_err() {
+            return;
+        }
+        let mut data = vec![0u8; BigEndian::read_u32(&size_b) as usize];
+        if stream.read_exact(&mut data).is_err() {
+            return;
+        }
+        requests.fetch_add(1, Ordering::SeqCst);
+        let (code, body) = respond(&data, commit_error, &clock);
+        if write_frame(&mut stream, code, &body).is_err() {
+            return;
+        }
+        served += 1;
+    }
+}
+
+// Response code and body to a request (message code followed by the message).
+fn respond(data: &[u8], commit_error: Option<u32>, clock: &Clock) -> (u8, Vec<u8>) {
+    match data[0] {
+        // start transaction
+        119 => {
+            let mut request = ApbStartTransaction::new();
+            request.merge_from_bytes(&data[1..]).unwrap();
+            clock.start(&request);
+            let mut resp = ApbStartTransactionResp::new();
+            resp.set_success(true);
+            resp.set_transaction_descriptor(transaction_descriptor());
+            (124, resp.write_to_bytes().unwrap())
+        }
+        // read objects
+        116 => {
+            let mut request = ApbReadObjects::new();
+            request.merge_from_bytes(&data[1..]).unwrap();
+            (126, read_objects(request.get_boundobjects()).write_to_bytes().unwrap())
+        }
+        // static read objects
+        123 => {
+            let mut request = ApbStaticReadObjects::new();
+            request.merge_from_bytes(&data[1..]).unwrap();
+            clock.start(request.get_transaction());
+            let mut commit = ApbCommitResp::new();
+            commit.set_success(true);
+            commit.set_commit_time(clock.now());
+            let mut resp = ApbStaticReadObjectsResp::new();
+            resp.set_objects(read_objects(request.get_objects()));
+            resp.set_committime(commit);
+            (128, resp.write_to_bytes().unwrap())
+        }
+        // commit, static update
+        121 | 122 => {
+            if data[0] == 122 {
+                let mut request = ApbStaticUpdateObjects::new();
+                request.merge_from_bytes(&data[1..]).unwrap();
+                clock.start(request.get_transaction());
+            }
+            let mut resp = ApbCommitResp::new();
+            resp.set_success(commit_error.is_none());
+            if let Some(code) = commit_error {
+                resp.set_errorcode(code);
+            }
+            resp.set_commit_time(clock.commit());
+            (127, resp.write_to_bytes().unwrap())
+        }
+        // update objects, rejected if an operation does not fit the type of its object
+        118 => {
+            let mut request = ApbUpdateObjects::new();
+            request.merge_from_bytes(&data[1..]).unwrap();
+            let mut resp = ApbOperationResp::new();
+            resp.set_success(request.get_updates().iter().all(fits_type));
+            if !resp.get_success() {
+                resp.set_errorcode(0);
+            }
+            (111, resp.write_to_bytes().unwrap())
+        }
+        // get connection descriptor
+        133 => {
+            let mut resp = ApbGetConnectionDescriptorResp::new();
+            resp.set_success(true);
+            resp.set_d(descriptor());
+            (134, resp.write_to_bytes().unwrap())
+        }
+        // connect to DCs, rejected unless every descriptor is the one of this server
+        131 => {
+            let mut request = ApbConnectToDCs::new();
+            request.merge_from_bytes(&data[1..]).unwrap();
+            let mut resp = ApbConnectToDCsResp::new();
+            resp.set_success(request.get_descriptors().iter().all(|d| *d == descriptor()));
+            if !resp.get_success() {
+                resp.set_errorcode(0);
+            }
+            (132, resp.write_to_bytes().unwrap())
+        }
+        // create DC, answered with an error response like a request Antidote failed to handle
+        129 => {
+            let mut resp = ApbErrorResp::new();
+            resp.set_errmsg(b"cannot create a DC".to_vec());
+            resp.set_errcode(0);
+            (0, resp.write_to_bytes().unwrap())
+        }
+        // update, abort and anything else
+        _ => {
+            let mut resp = ApbOperationResp::new();
+            resp.set_success(true);
+            (111, resp.write_to_bytes().unwrap())
+        }
+    }
+}
+
+fn fits_type(update: &ApbUpdateOp) -> bool {
+    let operation = update.get_operation();
+    match update.get_boundobject().get_field_type() {
+        _ if operation.has_resetop() => true,
+        CRDT_type::COUNTER | CRDT_type::FATCOUNTER | CRDT_type::BCOUNTER => operation.has_counterop(),
+        CRDT_type::ORSET | CRDT_type::RWSET => operation.has_setop(),
+        CRDT_type::LWWREG | CRDT_type::MVREG => operation.has_regop(),
+        CRDT_type::RRMAP | CRDT_type::GMAP => operation.has_mapop(),
+        CRDT_type::FLAG_EW | CRDT_type::FLAG_DW => operation.has_flagop(),
+    }
+}
+
+fn read_objects(objects: &[ApbBoundObject]) -> ApbReadObjectsResp {
+    let mut resp = ApbReadObjectsResp::new();
+    resp.set_success(true);
+    for object in objects {
+        let mut value = ApbReadObjectResp::new();
+        if matches!(object.get_field_type(), CRDT_type::COUNTER | CRDT_type::BCOUNTER) {
+            value.mut_counter().set_value(COUNTER_VALUE);
+        }
+        resp.mut_objects().push(value);
+    }
+    resp
+}
+
+/// In-memory stream answering like `FakeAntidote`: each complete request frame written to it
+/// queues the response to be read.
+pub struct MemoryTransport {
+    clock: Clock,
+    request: Vec<u8>,
+    response: VecDeque<u8>,
+    interrupt: Option<Arc<AtomicBool>>,
+    interrupted: bool,
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        MemoryTransport { clock: Clock::default(), request: Vec::new(), response: VecDeque::new(), interrupt: None, interrupted: false }
+    }
+
+    /// Once `interrupt` is set, the next read returns a single byte and the read after it fails with
+    /// `ErrorKind::Interrupted`, leaving the rest of the response unread like a cancelled read.
+    pub fn interruptible(interrupt: Arc<AtomicBool>) -> MemoryTransport {
+        MemoryTransport { interrupt: Some(interrupt), ..MemoryTransport::new() }
+    }
+
+    /// Starts with an operation response no request was sent for, so every response is read for the wrong request.
+    pub fn with_stray_response() -> MemoryTransport {
+        let mut transport = MemoryTransport::new();
+        let (code, body) = respond(&[0], None, &transport.clock);
+        write_frame(&mut transport.response, code, &body).unwrap();
+        transport
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.interrupted {
+            self.interrupted = false;
+            return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "read interrupted"));
+        }
+        let mut len = buf.len();
+        if let Some(interrupt) = &self.interrupt {
+            if interrupt.swap(false, Ordering::SeqCst) {
+                self.interrupted = true;
+                len = len.min(1);
+            }
+        }
+        let mut n = 0;
+        while n < len {
+            match self.response.pop_front() {
+                Some(b) => buf[n] = b,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.request.extend_from_slice(buf);
+        while self.request.len() >= 4 {
+            let size = BigEndian::read_u32(&self.request[0..4]) as usize;
+            if self.request.len() < 4 + size {
+                break;
+            }
+            let data: Vec<u8> = self.request.drain(..4 + size).skip(4).collect();
+            let (code, body) = respond(&data, None, &self.clock);
+            let mut frame = Vec::new();
+            write_frame(&mut frame, code, &body)?;
+            self.response.extend(frame);
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn write_frame(stream: &mut dyn Write, code: u8, body: &[u8]) -> std::io::Result<()> {
+    let mut buf = [0u8; 5];
+    BigEndian::write_u32(&mut buf[0..4], (body.len() + 1) as u32);
+    buf[4] = code;
+    stream.write_all(&buf)?;
+    stream.write_all(body)
+}
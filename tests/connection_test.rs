@@ -0,0 +1,2098 @@
+mod common;
+
+use std::io::{Error, ErrorKind, Read};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use protobuf::Message;
+use common::{COUNTER_VALUE, TX_START_TIME, FakeAntidote, MemoryTransport, commit_time};
+use antidote_rust_client::{AntidoteConnectionManager, Client, ClientBuilder, Connector, Host, HostRole, HostSelection, NodeStatus, PoolExhaustedMode, SharedClient,
+    TransactionLimitMode, TransactionDescriptor, Transport, new_client};
+use antidote_rust_client::error::{AntidoteError, ErrorCode};
+use antidote_rust_client::observer::{ConnectionEventListener, DiscardReason, LatencyObserver, Operation};
+use antidote_rust_client::antidote_pb::{ApbBoundObject, ApbGetMapResp, ApbMapEntry, ApbReadObjectResp, ApbReadObjectsResp, ApbStartTransactionResp, ApbUpdateOp,
+    CRDT_type};
+use antidote_rust_client::transactions::{BCounter, Bucket, CRDTReader, CRDTValue, DesiredMap, Key, CRDTUpdater, MapEntryKey, MapKind, MapReadResult, MapReadResultExtractor, MultiRead, Transaction,
+    TransactionMode, TypeRegistry, TypedKey, bcounter_dec, counter_inc, dedupe_and_merge, read_counter_across, reg_put, set_add, set_remove};
+
+
+#[test]
+fn test_reconnect_after_connection_closed() -> Result<(), Error> {
+    // the server drops every connection after two requests, like a host that moved to a new address
+    let server = FakeAntidote::start(Some(2));
+    let client = server.client();
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+    assert_eq!(1, server.accepted());
+
+    // the stale connection fails once and is discarded by the pool ...
+    assert!(client.start_transaction().is_err());
+
+    // ... and replaced by a freshly connected one
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    assert_eq!(2, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_reconnect_after_address_change() -> Result<(), Error> {
+    // the connector looks the host name up in a table on every connect, like connect_tcp asks the resolver
+    let old_pod = FakeAntidote::start(Some(2));
+    let new_pod = FakeAntidote::start(None);
+    let resolved = Arc::new(Mutex::new(old_pod.port));
+    let table = resolved.clone();
+    let connector: Connector = Arc::new(move |addr: &str| {
+        assert_eq!("antidote.svc:8087", addr);
+        let stream = std::net::TcpStream::connect(("127.0.0.1", *table.lock().unwrap()))?;
+        Ok(Box::new(stream) as Box<dyn Transport>)
+    });
+    let client = ClientBuilder::new()
+        .host(Host::new("antidote.svc", 8087))
+        .connector(connector)
+        .max_pool_size(1)
+        .build()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+
+    // the pod is rescheduled: the name now points to the new address and the old one closes its connections
+    *resolved.lock().unwrap() = new_pod.port;
+    assert!(client.start_transaction().is_err());
+
+    // the broken connection is replaced by one to the new address
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(1, old_pod.accepted());
+    assert_eq!(1, new_pod.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_large_update_framing() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("keyLargeSet".as_bytes().to_vec());
+
+    // a message far larger than the stream's internal buffer has to arrive as one frame
+    let elems: Vec<Vec<u8>> = (0..100000).map(|i: i32| i.to_be_bytes().to_vec()).collect();
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(set_add(&key, elems)))?;
+    tx.commit()?;
+    Ok(())
+}
+
+#[test]
+fn test_set_add_chunked() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("keyChunkedSet".as_bytes().to_vec());
+
+    let elems: Vec<Vec<u8>> = (0..250000).map(|i: i32| i.to_be_bytes().to_vec()).collect();
+    let mut tx = client.start_transaction()?;
+    bucket.set_add_chunked(&mut tx, &key, elems.clone(), 10000)?;
+    assert_eq!(ErrorKind::InvalidInput, bucket.set_add_chunked(&mut tx, &key, Vec::new(), 0).err().unwrap().kind());
+    tx.commit()?;
+    drop(tx);
+    let interactive_requests = server.requests();
+    // a static transaction sends each chunk as a transaction of its own
+    let mut tx = client.create_static_transaction()?;
+    bucket.set_add_chunked(&mut tx, &key, elems, 100000)?;
+
+    // asserts: start, 25 chunks and commit; then 3 static updates
+    assert_eq!(27, interactive_requests);
+    assert_eq!(30, server.requests());
+    Ok(())
+}
+
+#[test]
+fn test_host_introspection() {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    assert_eq!(1, client.host_count());
+    assert_eq!("127.0.0.1", client.hosts()[0].name);
+    assert_eq!(server.port, client.hosts()[0].port);
+}
+
+#[test]
+fn test_no_healthy_hosts() {
+    // ports nobody listens on
+    let mut hosts = Vec::new();
+    for _ in 0..2 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        hosts.push(Host::new("127.0.0.1", listener.local_addr().unwrap().port()));
+    }
+    let client = ClientBuilder::new()
+        .hosts(hosts)
+        .max_pool_size(1)
+        .checkout_deadline(Duration::from_millis(500))
+        .build()
+        .unwrap();
+
+    let start = Instant::now();
+    let err = client.start_transaction().err().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(ErrorKind::NotConnected, err.kind());
+    match &err {
+        AntidoteError::NoHealthyHosts(failures) => assert_eq!(2, failures.len()),
+        _ => panic!("expected NoHealthyHosts, got {}", err),
+    }
+}
+
+#[test]
+fn test_replace_host() -> Result<(), Error> {
+    let old_server = FakeAntidote::start(None);
+    let new_server = FakeAntidote::start(None);
+    let client = old_server.client();
+
+    // a transaction running on the old host while it is replaced
+    let mut running = client.start_transaction()?;
+    let address = format!("127.0.0.1:{}", old_server.port);
+    client.replace_host(&address, Host::new("127.0.0.1", new_server.port))?;
+    running.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(new_server.port, client.hosts()[0].port);
+    assert_eq!(1, new_server.accepted());
+    assert_eq!(ErrorKind::NotFound, client.replace_host("unknown", Host::new("127.0.0.1", 1)).err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_replace_host_shared() -> Result<(), Error> {
+    let old_server = FakeAntidote::start(None);
+    let new_server = FakeAntidote::start(None);
+    let client = SharedClient::new(old_server.client());
+
+    // replaced by another thread while this one keeps using the client
+    let other = client.clone();
+    let new_port = new_server.port;
+    let address = format!("127.0.0.1:{}", old_server.port);
+    thread::spawn(move || other.replace_host(&address, Host::new("127.0.0.1", new_port))).join().unwrap()?;
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(new_server.port, client.hosts()[0].port);
+    assert_eq!(1, new_server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_replace_host_ambiguous() -> Result<(), Error> {
+    let first = FakeAntidote::start(None);
+    let second = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", first.port))
+        .host(Host::new("127.0.0.1", second.port))
+        .build()?;
+
+    // the name matches both hosts, the address only the second
+    let ambiguous = client.replace_host("127.0.0.1", Host::new("localhost", first.port)).err().unwrap();
+    let duplicate = client.replace_host(&format!("127.0.0.1:{}", second.port), Host::new("127.0.0.1", first.port)).err().unwrap();
+    client.replace_host(&format!("127.0.0.1:{}", second.port), Host::new("localhost", second.port))?;
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidInput, ambiguous.kind());
+    assert_eq!(ErrorKind::InvalidInput, duplicate.kind());
+    assert_eq!("127.0.0.1", client.hosts()[0].name);
+    assert_eq!("localhost", client.hosts()[1].name);
+    Ok(())
+}
+
+#[test]
+fn test_reconnect_all() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = SharedClient::new(server.client());
+
+    // a transaction running on the old pool while another thread rebuilds the pools
+    let mut running = client.start_transaction()?;
+    let other = client.clone();
+    thread::spawn(move || other.reconnect_all()).join().unwrap();
+    running.commit()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(server.port, client.hosts()[0].port);
+    // the new pool opened its own connection
+    assert_eq!(2, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_transaction_aborted() -> Result<(), Error> {
+    for (code, retryable) in [(ErrorCode::Timeout, true), (ErrorCode::NoPermissions, false), (ErrorCode::Aborted, true)].iter() {
+        let server = FakeAntidote::start_with(None, Some(code.value()));
+        let client = server.client();
+
+        let mut tx = client.start_transaction()?;
+        let err = tx.commit().err().unwrap();
+        match &err {
+            AntidoteError::TransactionAborted { code: c, retryable: r } => {
+                assert_eq!(code, c);
+                assert_eq!(retryable, r);
+            }
+            _ => panic!("expected TransactionAborted, got {}", err),
+        }
+    }
+
+    // unknown error codes are not aborts
+    let server = FakeAntidote::start_with(None, Some(0));
+    let client = server.client();
+    let mut tx = client.start_transaction()?;
+    let err = tx.commit().err().unwrap();
+    assert!(matches!(err, AntidoteError::OperationFailed { code: ErrorCode::Unknown, message: None }));
+    assert!(err.to_string().contains("unknown (error code 0)"));
+    // codes of newer versions keep their value
+    assert_eq!(ErrorCode::Other(7), ErrorCode::from(7));
+    assert_eq!(7, ErrorCode::from(7).value());
+    Ok(())
+}
+
+#[test]
+fn test_bounded_counter() -> Result<(), Error> {
+    // Antidote refuses the decrement of a replica without enough rights, the fake when committing
+    let server = FakeAntidote::start_with(None, Some(ErrorCode::NoPermissions.value()));
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key: TypedKey<BCounter> = TypedKey::new("bounded".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let value = bucket.read_bcounter(&mut tx, &key)?;
+    // the fake rejects operations that do not fit the type of their object
+    bucket.update(&mut tx, vec!(bcounter_dec(&key, value + 1)))?;
+    let err = tx.commit().err().unwrap();
+
+    // asserts
+    assert_eq!(i64::from(COUNTER_VALUE), value);
+    assert!(matches!(err, AntidoteError::TransactionAborted { code: ErrorCode::NoPermissions, retryable: false }));
+    Ok(())
+}
+
+#[test]
+fn test_commit_detailed() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let mut tx = client.start_transaction()?;
+    let info = tx.commit_detailed()?;
+
+    // asserts
+    assert_eq!(commit_time(1), info.commit_time);
+    assert!(info.duration > Duration::from_secs(0));
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_after_commit() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let first = tx.commit_detailed()?;
+    drop(tx);
+    let mut tx = client.transaction_builder().timestamp(first.commit_time.clone()).start()?;
+    tx.commit()?;
+    drop(tx);
+    let mut tx = client.create_static_transaction()?;
+    tx.static_read_your_write(&bucket, vec!(counter_inc(&key, 1)), &MultiRead::new().add(&bucket, &key, CRDT_type::COUNTER))?;
+
+    // asserts
+    assert_eq!(commit_time(1), first.commit_time);
+    // the second transaction reads from a snapshot including the first one,
+    // the static read from a snapshot including the static update (the third commit)
+    assert_eq!(vec!(None, Some(1), None, Some(3)), server.clock.snapshots());
+    Ok(())
+}
+
+#[test]
+fn test_commit_twice() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let mut tx = client.start_transaction()?;
+    let first = tx.commit_detailed()?;
+    let second = tx.commit_detailed()?;
+    tx.abort()?;
+
+    // asserts
+    assert!(tx.committed);
+    assert_eq!(commit_time(1), first.commit_time);
+    assert!(second.commit_time.is_empty());
+    // start and the first commit only
+    assert_eq!(2, server.requests());
+    Ok(())
+}
+
+#[test]
+fn test_commit_after_failed_commit() -> Result<(), Error> {
+    let server = FakeAntidote::start_with(None, Some(3));
+    let client = server.client();
+
+    let mut tx = client.start_transaction()?;
+    let first = tx.commit().err().unwrap();
+    let retry = tx.commit().err().unwrap();
+
+    // asserts
+    assert!(matches!(first, AntidoteError::TransactionAborted { .. }));
+    assert!(matches!(retry, AntidoteError::PreviousCommitFailed));
+    assert!(retry.to_string().contains("previous commit failed"));
+    assert!(!tx.committed);
+    // the retry is rejected without a request
+    assert_eq!(2, server.requests());
+    Ok(())
+}
+
+#[test]
+fn test_commit_after_failed_update() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let mut updates = vec!(counter_inc(&Key("counter".as_bytes().to_vec()), 1), counter_inc(&Key("set".as_bytes().to_vec()), 1))
+        .into_iter().map(|u| {
+            let mut recorder = RecordingTransaction { updates: Vec::new() };
+            bucket.update(&mut recorder, vec!(u)).unwrap();
+            recorder.updates.remove(0)
+        }).collect::<Vec<ApbUpdateOp>>();
+    // a counter increment on a set is rejected
+    updates[1].mut_boundobject().set_field_type(CRDT_type::ORSET);
+
+    let mut tx = client.start_transaction()?;
+    let failed = Transaction::update(&mut tx, &updates).err().unwrap();
+    let requests = server.requests();
+    let commit = tx.commit().err().unwrap();
+    let read = bucket.read_counter(&mut tx, &Key("counter".as_bytes().to_vec())).err().unwrap();
+    let rejected_requests = server.requests() - requests;
+    tx.abort()?;
+
+    // asserts
+    assert!(failed.to_string().contains("error code 0"));
+    assert!(matches!(commit, AntidoteError::TransactionPoisoned));
+    assert!(matches!(read, AntidoteError::TransactionPoisoned));
+    assert!(commit.to_string().contains("previous update failed"));
+    assert!(!tx.committed);
+    // commit and read are rejected without a request
+    assert_eq!(0, rejected_requests);
+    Ok(())
+}
+
+#[test]
+fn test_update_no_ack() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+    let mut recorder = RecordingTransaction { updates: Vec::new() };
+    bucket.update(&mut recorder, vec!(counter_inc(&key, 1)))?;
+    let increment = recorder.updates.remove(0);
+    // a counter increment on a set is rejected
+    let mut rejected = increment.clone();
+    rejected.mut_boundobject().set_field_type(CRDT_type::ORSET);
+
+    let mut tx = client.transaction_builder().update_no_ack(true).start()?;
+    for _ in 0..3 {
+        bucket.update(&mut tx, vec!(counter_inc(&key, 1)))?;
+    }
+    let value = bucket.read_counter(&mut tx, &key)?;
+    let info = tx.commit_detailed()?;
+    drop(tx);
+
+    // the failed update only surfaces on commit
+    let mut failing = client.transaction_builder().update_no_ack(true).start()?;
+    Transaction::update(&mut failing, &vec!(increment.clone()))?;
+    Transaction::update(&mut failing, &vec!(rejected))?;
+    Transaction::update(&mut failing, &vec!(increment))?;
+    let failed = failing.commit().err().unwrap();
+    failing.abort()?;
+    drop(failing);
+
+    // the connection was left in sync and is reused
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(counter_inc(&key, 1)))?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(COUNTER_VALUE, value);
+    assert_eq!(commit_time(1), info.commit_time);
+    match &failed {
+        AntidoteError::DeferredUpdateFailed { update, source } => {
+            assert_eq!(1, *update);
+            assert!(source.to_string().contains("error code 0"));
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(1, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_max_operations() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.transaction_builder().max_operations(3).start()?;
+    let mut result = Ok(());
+    for _ in 0..10 {
+        result = bucket.update(&mut tx, vec!(counter_inc(&key, 1)));
+        if result.is_err() {
+            break;
+        }
+    }
+    let count = tx.operation_count();
+    let read = bucket.read_counter(&mut tx, &key).err().unwrap();
+    // refused operations are not sent, the transaction still commits
+    tx.commit()?;
+
+    // asserts
+    let err = result.err().unwrap();
+    assert!(matches!(err, AntidoteError::TransactionTooLarge(3)));
+    assert!(matches!(read, AntidoteError::TransactionTooLarge(3)));
+    assert_eq!(3, count);
+    // start, 3 updates and the commit
+    assert_eq!(5, server.requests());
+    Ok(())
+}
+
+#[test]
+fn test_commit_all() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let failing_server = FakeAntidote::start_with(None, Some(3));
+    let client = ClientBuilder::new().host(Host::new("127.0.0.1", server.port)).max_pool_size(2).build()?;
+    let failing_client = failing_server.client();
+
+    let commit_times = client.commit_all(vec!(client.start_transaction()?, client.start_transaction()?))?;
+    let requests = server.requests();
+    let txns = vec!(client.start_transaction()?, failing_client.start_transaction()?, client.start_transaction()?);
+    let err = client.commit_all(txns).err().unwrap();
+
+    // asserts
+    assert_eq!(vec!(commit_time(1), commit_time(2)), commit_times);
+    match &err {
+        AntidoteError::PartialCommit { commit_times, source } => {
+            assert_eq!(vec!(commit_time(3)), *commit_times);
+            assert!(err.to_string().starts_with("Commit of transaction 1 failed"), "{}", err);
+            assert!(matches!(**source, AntidoteError::TransactionAborted { code: ErrorCode::Aborted, .. }));
+        }
+        _ => panic!("expected PartialCommit, got {}", err),
+    }
+    // two starts, the commit of the first transaction and the abort of the last one
+    assert_eq!(4, server.requests() - requests);
+    Ok(())
+}
+
+#[test]
+fn test_consistent_read() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let objects = MultiRead::new()
+        .add(&bucket, &Key("counter".as_bytes().to_vec()), CRDT_type::COUNTER)
+        .add(&bucket, &Key("set".as_bytes().to_vec()), CRDT_type::ORSET);
+
+    let values = client.consistent_read(&objects)?;
+
+    // asserts
+    assert!(matches!(values[0], CRDTValue::Counter(COUNTER_VALUE)));
+    assert!(matches!(&values[1], CRDTValue::Set(elems) if elems.is_empty()));
+    // a single static read
+    assert_eq!(1, server.requests());
+    Ok(())
+}
+
+#[test]
+fn test_invalid_hosts() {
+    let bad_port = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", 0))
+        .build();
+    let duplicate = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", 8087))
+        .host(Host::new("127.0.0.1", 8087))
+        .build();
+
+    // asserts
+    let err = bad_port.err().unwrap();
+    assert_eq!(ErrorKind::InvalidInput, err.kind());
+    assert!(err.to_string().contains("127.0.0.1"));
+    assert_eq!(ErrorKind::InvalidInput, duplicate.err().unwrap().kind());
+    #[allow(deprecated)]
+    {
+        assert_eq!(8087, Host::from_i32_port("127.0.0.1", 8087).unwrap().port);
+        assert_eq!(ErrorKind::InvalidInput, Host::from_i32_port("127.0.0.1", -5).err().unwrap().kind());
+        assert_eq!(ErrorKind::InvalidInput, Host::from_i32_port("127.0.0.1", 65536).err().unwrap().kind());
+    }
+
+    let err = new_client(Vec::new()).err().unwrap();
+    assert_eq!(ErrorKind::InvalidInput, err.kind());
+    assert!(matches!(err, AntidoteError::NoHostsConfigured));
+}
+
+#[test]
+fn test_defaults() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let host = Host { name: String::from("127.0.0.1"), port: server.port, ..Default::default() };
+    let client = ClientBuilder::default().host(host).max_pool_size(1).build()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+    let accepted = server.accepted();
+    // localhost:port with default settings
+    let local = Client::local(server.port)?;
+    local.node_status()?;
+
+    // asserts
+    let default = Host::default();
+    assert_eq!(("localhost", 8087, HostRole::Any), (default.name.as_str(), default.port, default.role));
+    assert_eq!(1, accepted);
+    assert_eq!(("localhost", server.port), (local.hosts()[0].name.as_str(), local.hosts()[0].port));
+    Ok(())
+}
+
+// Records the operations and trace ids it is notified about.
+struct TraceRecorder {
+    seen: Mutex<Vec<(Operation, Option<String>)>>,
+}
+
+impl LatencyObserver for TraceRecorder {
+    fn observe(&self, _op: Operation, _elapsed: Duration, _success: bool) {
+    }
+
+    fn observe_traced(&self, op: Operation, _elapsed: Duration, _success: bool, trace_id: Option<&str>) {
+        self.seen.lock().unwrap().push((op, trace_id.map(String::from)));
+    }
+}
+
+#[test]
+fn test_trace_id() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let recorder = Arc::new(TraceRecorder { seen: Mutex::new(Vec::new()) });
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .latency_observer(recorder.clone())
+        .build()?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("keySet".as_bytes().to_vec());
+
+    let mut tx = client.transaction_with_trace_id("trace-1")?;
+    assert_eq!(Some("trace-1"), tx.trace_id());
+    bucket.update(&mut tx, vec!(set_add(&key, vec!("A".as_bytes().to_vec()))))?;
+    tx.commit()?;
+
+    // asserts
+    let seen = recorder.seen.lock().unwrap();
+    let trace = Some(String::from("trace-1"));
+    assert_eq!(vec!(
+        (Operation::Checkout, None),
+        (Operation::StartTransaction, trace.clone()),
+        (Operation::Update, trace.clone()),
+        (Operation::Commit, trace),
+    ), *seen);
+    Ok(())
+}
+
+#[test]
+fn test_builder_connector() -> Result<(), Error> {
+    let addresses = Arc::new(Mutex::new(Vec::new()));
+    let addresses_ref = addresses.clone();
+    let connector: Connector = Arc::new(move |addr: &str| {
+        addresses_ref.lock().unwrap().push(String::from(addr));
+        Ok(Box::new(MemoryTransport::new()) as Box<dyn Transport>)
+    });
+    let client = ClientBuilder::new()
+        .host(Host::new("memory", 1))
+        .max_pool_size(1)
+        .connector(connector)
+        .build()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+    // replacement pools connect through the connector as well
+    client.replace_host("memory:1", Host::new("memory", 2))?;
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+
+    // asserts
+    assert_eq!(vec!("memory:1", "memory:2"), *addresses.lock().unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_existing_pools() -> Result<(), Error> {
+    let connector: Connector = Arc::new(|_addr: &str| Ok(Box::new(MemoryTransport::new()) as Box<dyn Transport>));
+    let manager = AntidoteConnectionManager::with_connector(String::from("memory:1"), connector);
+    let pool = r2d2::Pool::builder().max_size(1).build(manager).unwrap();
+    let client = Client::with_existing_pools(vec!(Host::new("memory", 1)), vec!(pool))?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("keySet".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(set_add(&key, vec!("A".as_bytes().to_vec()))))?;
+    let info = tx.commit_detailed()?;
+
+    // asserts
+    assert_eq!(commit_time(1), info.commit_time);
+    assert_eq!(ErrorKind::InvalidInput, Client::with_existing_pools(Vec::new(), vec!(r2d2::Pool::builder()
+        .build_unchecked(AntidoteConnectionManager::new(String::from("127.0.0.1:1"))))).err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_quarantine_failing_host() -> Result<(), Error> {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", port))
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .checkout_deadline(Duration::from_secs(2))
+        .failure_threshold(1)
+        .quarantine_period(Duration::from_secs(60))
+        .build()?;
+
+    // the unreachable first host costs up to half the deadline once ...
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+
+    // ... then it is quarantined and skipped
+    let start = Instant::now();
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    assert!(start.elapsed() < Duration::from_millis(500));
+    Ok(())
+}
+
+#[test]
+fn test_quarantine_probe() -> Result<(), Error> {
+    // a host that refuses connections until it is back up
+    let up = Arc::new(AtomicBool::new(false));
+    let state = up.clone();
+    let connector: Connector = Arc::new(move |addr: &str| match state.load(Ordering::SeqCst) {
+        true => Ok(Box::new(MemoryTransport::new()) as Box<dyn Transport>),
+        false => Err(Error::new(ErrorKind::ConnectionRefused, format!("{} refused", addr))),
+    });
+    let client = ClientBuilder::new()
+        .host(Host::new("memory", 1))
+        .connector(connector)
+        .max_pool_size(1)
+        .connection_timeout(Duration::from_millis(400))
+        .checkout_deadline(Duration::from_millis(200))
+        .failure_threshold(1)
+        .quarantine_period(Duration::from_secs(60))
+        .build()?;
+
+    // the pool's background connects fail and quarantine the host
+    let start = Instant::now();
+    let quarantined = loop {
+        let err = client.start_transaction().err().unwrap();
+        if err.to_string().contains("quarantined") || start.elapsed() > Duration::from_secs(5) {
+            break err;
+        }
+    };
+    up.store(true, Ordering::SeqCst);
+
+    // the next background connect succeeds and reinstates the host long before the quarantine period is over
+    let start = Instant::now();
+    let mut tx = loop {
+        match client.start_transaction() {
+            Ok(tx) => break tx,
+            Err(e) if start.elapsed() > Duration::from_secs(5) => return Err(e.into()),
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    tx.commit()?;
+
+    // asserts
+    assert!(quarantined.to_string().contains("quarantined"), "{}", quarantined);
+    Ok(())
+}
+
+#[test]
+fn test_huge_durations() -> Result<(), Error> {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let server = FakeAntidote::start(None);
+    let deadline = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .checkout_deadline(Duration::MAX)
+        .build();
+    // quarantined "forever" without overflowing the clock
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", port))
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .checkout_deadline(Duration::from_secs(2))
+        .failure_threshold(1)
+        .quarantine_period(Duration::MAX)
+        .build()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidInput, deadline.err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_host_selection() -> Result<(), Error> {
+    let mut transactions_per_host = Vec::new();
+    for selection in [HostSelection::InOrder, HostSelection::RoundRobin] {
+        let servers = [FakeAntidote::start(None), FakeAntidote::start(None), FakeAntidote::start(None)];
+        let client = ClientBuilder::new()
+            .hosts(servers.iter().map(|s| Host::new("127.0.0.1", s.port)).collect())
+            .max_pool_size(1)
+            .host_selection(selection)
+            .build()?;
+        for _ in 0..6 {
+            let mut tx = client.start_transaction()?;
+            tx.commit()?;
+        }
+        // start and commit of each transaction
+        transactions_per_host.push(servers.iter().map(|s| s.requests() / 2).collect::<Vec<usize>>());
+    }
+
+    // asserts
+    assert_eq!(vec!(vec!(6, 0, 0), vec!(2, 2, 2)), transactions_per_host);
+    Ok(())
+}
+
+#[test]
+fn test_shared_client() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = SharedClient::new(server.client());
+
+    let mut children = Vec::new();
+    for _ in 0..3 {
+        let client = client.clone();
+        children.push(thread::spawn(move || -> Result<(), Error> {
+            for _ in 0..10 {
+                let mut tx = client.start_transaction()?;
+                tx.commit()?;
+            }
+            Ok(())
+        }));
+    }
+    for child in children {
+        child.join().unwrap()?;
+    }
+
+    // static transactions only need a shared reference as well
+    let mut tx = client.create_static_transaction()?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    bucket.update(&mut tx, vec!(set_add(&Key("keySet".as_bytes().to_vec()), vec!("A".as_bytes().to_vec()))))?;
+    Ok(())
+}
+
+#[test]
+fn test_socket_buffer_sizes() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .send_buffer_size(64 << 10)
+        .recv_buffer_size(128 << 10)
+        .build()?;
+    // the sizes the OS applies for the requested ones (Linux doubles them)
+    let probe = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None)?;
+    let default_sizes = (probe.send_buffer_size()?, probe.recv_buffer_size()?);
+    probe.set_send_buffer_size(64 << 10)?;
+    probe.set_recv_buffer_size(128 << 10)?;
+    let expected = (probe.send_buffer_size()?, probe.recv_buffer_size()?);
+
+    let mut tx = client.start_transaction()?;
+    let applied = client_sockets(server.port)?;
+    tx.commit()?;
+
+    // asserts
+    assert_ne!(default_sizes, expected);
+    assert!(!applied.is_empty());
+    for sizes in applied {
+        assert_eq!(expected, sizes);
+    }
+    Ok(())
+}
+
+// Send and receive buffer sizes of the sockets of this process connected to the given port.
+#[cfg(target_os = "linux")]
+fn client_sockets(port: u16) -> Result<Vec<(usize, usize)>, Error> {
+    use std::os::fd::BorrowedFd;
+    let mut sizes = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/fd")? {
+        let fd: i32 = match entry?.file_name().to_str().and_then(|n| n.parse().ok()) {
+            Some(fd) => fd,
+            None => continue,
+        };
+        // the fd stays open while it is borrowed, the connection is held by the running transaction
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        let socket = socket2::SockRef::from(&fd);
+        let connected = socket.peer_addr().ok().and_then(|a| a.as_socket()).is_some_and(|a| a.port() == port);
+        if connected {
+            sizes.push((socket.send_buffer_size()?, socket.recv_buffer_size()?));
+        }
+    }
+    Ok(sizes)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn client_sockets(_port: u16) -> Result<Vec<(usize, usize)>, Error> {
+    Err(Error::new(ErrorKind::Unsupported, "listing the sockets of the process needs /proc"))
+}
+
+#[test]
+fn test_connect_timeout() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("localhost", server.port))
+        .max_pool_size(1)
+        .connect_timeout(Duration::from_millis(200))
+        .build()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Records when connects fail.
+struct ConnectFailures {
+    at: Mutex<Vec<Instant>>,
+}
+
+impl ConnectionEventListener for ConnectFailures {
+    fn on_connect_failed(&self, _host: &str, _error: &str) {
+        self.at.lock().unwrap().push(Instant::now());
+    }
+}
+
+#[test]
+fn test_connect_timeout_unreachable() -> Result<(), Error> {
+    // setup: a listener with a full accept queue drops new SYNs, so connects hang like to a blackholed address
+    let listener = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None)?;
+    listener.bind(&"127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap().into())?;
+    listener.listen(0)?;
+    let addr = listener.local_addr()?.as_socket().unwrap();
+    let _queued = std::net::TcpStream::connect(addr)?;
+    let failures = Arc::new(ConnectFailures { at: Mutex::new(Vec::new()) });
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", addr.port()))
+        .max_pool_size(1)
+        .min_idle(Some(0))
+        .failure_threshold(0)
+        .connect_timeout(Duration::from_millis(200))
+        .connection_timeout(Duration::from_secs(1))
+        .connection_event_listener(failures.clone())
+        .build()?;
+
+    let start = Instant::now();
+    let result = client.start_transaction();
+
+    // asserts: the first connect gives up after about the connect timeout, not the OS's connect timeout
+    assert!(result.is_err());
+    let first = *failures.at.lock().unwrap().first().expect("no failed connect");
+    let elapsed = first.duration_since(start);
+    assert!(elapsed >= Duration::from_millis(150) && elapsed < Duration::from_millis(800), "connect failed after {:?}", elapsed);
+    Ok(())
+}
+
+#[test]
+fn test_connection_budget() -> Result<(), Error> {
+    let servers: Vec<FakeAntidote> = (0..3).map(|_| FakeAntidote::start(None)).collect();
+    let hosts: Vec<Host> = servers.iter().map(|s| Host::new("127.0.0.1", s.port)).collect();
+    let client = ClientBuilder::new()
+        .hosts(hosts.clone())
+        .max_total_connections(7)
+        .build()?;
+
+    let tx = client.start_transaction()?;
+    assert_eq!(1, client.in_use_connections());
+    drop(tx);
+
+    // the pools fill up to 7 / 3 = 2 connections each
+    let start = Instant::now();
+    while client.open_connections() < 6 && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    // asserts
+    assert_eq!(6, client.open_connections());
+    assert_eq!(0, client.in_use_connections());
+    assert_eq!(ErrorKind::InvalidInput, ClientBuilder::new().hosts(hosts).max_total_connections(2).build().err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_connection_budget_limits() -> Result<(), Error> {
+    // min_idle is lowered to the pool size of 2 the budget leaves each of the two hosts
+    let others: Vec<FakeAntidote> = (0..2).map(|_| FakeAntidote::start(None)).collect();
+    let lowered = ClientBuilder::new()
+        .hosts(others.iter().map(|s| Host::new("127.0.0.1", s.port)).collect())
+        .min_idle(Some(10))
+        .max_total_connections(4)
+        .build();
+    // 2 pooled connections and 1 of the 3 overflow connections fit the budget of 3
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(2)
+        .min_idle(Some(0))
+        .max_total_connections(3)
+        .pool_exhausted_mode(PoolExhaustedMode::Overflow(3))
+        .checkout_deadline(Duration::from_millis(200))
+        .build()?;
+
+    let held: Vec<_> = (0..3).map(|_| client.start_transaction()).collect::<Result<_, _>>()?;
+    let beyond = client.start_transaction().err().unwrap();
+    drop(held);
+
+    // asserts
+    assert!(lowered.is_ok());
+    assert!(matches!(beyond, AntidoteError::PoolTimeout(_)));
+    assert_eq!(3, server.accepted());
+    Ok(())
+}
+
+// Library code written against the trait, finalizing whatever transaction it gets.
+fn add_and_commit(tx: &mut dyn Transaction, bucket: &Bucket, key: &Key) -> Result<(), Error> {
+    bucket.update(tx, vec!(set_add(key, vec!("A".as_bytes().to_vec()))))?;
+    Ok(tx.commit()?)
+}
+
+#[test]
+fn test_commit_on_trait() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("keySet".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    add_and_commit(&mut tx, &bucket, &key)?;
+    drop(tx);
+    let mut tx = client.create_static_transaction()?;
+    add_and_commit(&mut tx, &bucket, &key)?;
+    Transaction::abort(&mut tx)?;
+    Ok(())
+}
+
+#[test]
+fn test_connection_descriptor_cache() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let descriptor = client.get_connection_descriptor()?;
+    let cached = client.get_connection_descriptor()?;
+    assert_eq!(1, server.requests());
+    let refreshed = client.refresh_connection_descriptor()?;
+
+    // asserts
+    assert_eq!(common::descriptor(), descriptor);
+    assert_eq!(descriptor, cached);
+    assert_eq!(descriptor, refreshed);
+    assert_eq!(2, server.requests());
+    Ok(())
+}
+
+#[test]
+fn test_connect_to_dcs_failed() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    client.connect_to_dcs(vec!(common::descriptor()))?;
+    let err = client.connect_to_dcs(vec!("not a descriptor".as_bytes().to_vec())).err().unwrap();
+
+    // asserts
+    assert!(matches!(err, AntidoteError::OperationFailed { code: ErrorCode::Unknown, message: None }));
+    assert_eq!(ErrorKind::Other, err.kind());
+    Ok(())
+}
+
+#[test]
+fn test_error_response() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let err = client.create_dc(vec!(String::from("antidote@127.0.0.1"))).err().unwrap();
+    // the error response answered the request, the connection stays in sync
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    match &err {
+        AntidoteError::OperationFailed { code: ErrorCode::Unknown, message: Some(message) } => assert_eq!("cannot create a DC", message),
+        _ => panic!("expected OperationFailed, got {}", err),
+    }
+    assert!(err.to_string().contains("unknown (error code 0): cannot create a DC"));
+    assert_eq!(1, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_error_conversions() {
+    let err = AntidoteError::from(Error::new(ErrorKind::UnexpectedEof, "closed"));
+    assert!(matches!(err, AntidoteError::Io(ref e) if e.kind() == ErrorKind::UnexpectedEof));
+    assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+
+    // client errors survive the round trip through an io error, I/O errors are not wrapped again
+    let wrapped: Error = AntidoteError::PoolExhausted.into();
+    assert_eq!(ErrorKind::WouldBlock, wrapped.kind());
+    assert!(matches!(AntidoteError::from(wrapped), AntidoteError::PoolExhausted));
+    let unwrapped: Error = AntidoteError::Io(Error::new(ErrorKind::ConnectionRefused, "refused")).into();
+    assert_eq!(ErrorKind::ConnectionRefused, unwrapped.kind());
+    assert!(AntidoteError::downcast(&unwrapped).is_none());
+}
+
+#[test]
+fn test_node_status() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let nodes = client.node_status()?;
+
+    // asserts
+    assert_eq!(vec!(NodeStatus {
+        publishers: vec!(String::from("127.0.0.1:8086")),
+        log_readers: vec!(String::from("127.0.0.1:8085")),
+    }), nodes);
+    // fetched again each time
+    client.node_status()?;
+    assert_eq!(2, server.requests());
+    Ok(())
+}
+
+struct EventRecorder {
+    events: Mutex<Vec<String>>,
+}
+
+impl ConnectionEventListener for EventRecorder {
+    fn on_created(&self, _host: &str) {
+        self.events.lock().unwrap().push(String::from("created"));
+    }
+
+    fn on_checked_out(&self, _host: &str) {
+        self.events.lock().unwrap().push(String::from("checked_out"));
+    }
+
+    fn on_returned(&self, _host: &str) {
+        self.events.lock().unwrap().push(String::from("returned"));
+    }
+
+    fn on_discarded(&self, _host: &str, reason: DiscardReason) {
+        self.events.lock().unwrap().push(format!("discarded {:?}", reason));
+    }
+}
+
+#[test]
+fn test_connection_event_listener() -> Result<(), Error> {
+    // the server drops every connection after two requests, so the second transaction finds it broken
+    let server = FakeAntidote::start(Some(2));
+    let recorder = Arc::new(EventRecorder { events: Mutex::new(Vec::new()) });
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .connection_event_listener(recorder.clone())
+        .build()?;
+
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+    assert!(client.start_transaction().is_err());
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+
+    // asserts
+    let events = recorder.events.lock().unwrap().clone();
+    assert_eq!(vec!("created", "checked_out", "returned", "checked_out", "discarded Broken", "created", "checked_out", "returned"), events);
+    Ok(())
+}
+
+#[test]
+fn test_no_pool() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .no_pool()
+        .build()?;
+
+    // nothing is opened in advance
+    thread::sleep(Duration::from_millis(50));
+    let accepted_before = server.accepted();
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+    let in_use = client.in_use_connections();
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(tx);
+
+    // asserts
+    assert_eq!(0, accepted_before);
+    assert_eq!(0, in_use);
+    // every transaction opened its own connection
+    assert_eq!(2, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_transaction_descriptor() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let mut tx = client.start_transaction()?;
+    let hex = tx.descriptor_hex();
+    let descriptor = tx.descriptor()?;
+    let debug = format!("{:?}", tx);
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(hex.len(), 2 * tx.tx_id.len());
+    assert!(hex.starts_with("8368036400057478"));
+    assert!(debug.contains(&hex));
+    assert_eq!(TransactionDescriptor {
+        local_start_time: TX_START_TIME,
+        server_node: Some(String::from("antidote@127.0.0.1")),
+        server_process: String::from("<0.123.0>"),
+    }, descriptor);
+    Ok(())
+}
+
+#[test]
+fn test_transaction_descriptor_nested_too_deeply() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let mut tx = client.start_transaction()?;
+    let tx_id = tx.tx_id.clone();
+    // a tuple nesting a tuple 100000 times, decoding it recursively would overflow the stack
+    let mut nested = vec!(131u8);
+    for _ in 0..100000 {
+        nested.extend_from_slice(&[104, 1]);
+    }
+    nested.push(106);
+    tx.tx_id = nested;
+    let err = tx.descriptor().err().unwrap();
+    tx.tx_id = tx_id;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidData, err.kind());
+    assert!(err.to_string().contains("nested too deeply"));
+    Ok(())
+}
+
+#[test]
+fn test_read_counter_projected() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let read = tx.read_counter_projected(&bucket, &key)?;
+    bucket.update(&mut tx, vec![counter_inc(&key, 3), counter_inc(&key, -1)])?;
+    let requests = server.requests();
+    let projected = tx.read_counter_projected(&bucket, &key)?;
+    let projected_requests = server.requests() - requests;
+    // counters not read in this transaction yet are read from Antidote
+    let other = tx.read_counter_projected(&bucket, &Key("other".as_bytes().to_vec()))?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(COUNTER_VALUE, read);
+    assert_eq!(COUNTER_VALUE + 2, projected);
+    assert_eq!(0, projected_requests);
+    assert_eq!(COUNTER_VALUE, other);
+    assert_eq!(5, server.requests());
+    Ok(())
+}
+
+#[test]
+fn test_read_counter_projected_other_type() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+    // a fat counter with the bucket and key of the counter
+    let mut fat_inc = ApbUpdateOp::new();
+    fat_inc.mut_boundobject().set_key(key.0.clone());
+    fat_inc.mut_boundobject().set_field_type(CRDT_type::FATCOUNTER);
+    fat_inc.mut_boundobject().set_bucket(bucket.bucket.clone());
+    fat_inc.mut_operation().mut_counterop().set_inc(5);
+
+    let mut tx = client.start_transaction()?;
+    tx.read_counter_projected(&bucket, &key)?;
+    bucket.update(&mut tx, vec![counter_inc(&key, 1)])?;
+    tx.update(&vec!(fat_inc))?;
+    let projected = tx.read_counter_projected(&bucket, &key)?;
+    tx.commit()?;
+
+    // asserts: only the increment of the counter itself is projected
+    assert_eq!(COUNTER_VALUE + 1, projected);
+    Ok(())
+}
+
+#[test]
+fn test_read_counter_across() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let shards: Vec<Bucket> = (0..3).map(|i| Bucket::new(format!("shard{}", i).into_bytes())).collect();
+
+    let mut tx = client.start_transaction()?;
+    let requests = server.requests();
+    let sum = read_counter_across(&mut tx, &shards, &Key("visits".as_bytes().to_vec()))?;
+    let read_requests = server.requests() - requests;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(3 * i64::from(COUNTER_VALUE), sum);
+    assert_eq!(1, read_requests);
+    Ok(())
+}
+
+#[test]
+fn test_registered_types() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let types = TypeRegistry::new()
+        .prefix("count:".as_bytes().to_vec(), CRDT_type::COUNTER)
+        .prefix("count:set:".as_bytes().to_vec(), CRDT_type::ORSET)
+        .key("count:reg".as_bytes().to_vec(), CRDT_type::LWWREG);
+    let bucket = Bucket::new("bucket".as_bytes().to_vec()).with_types(Arc::new(types));
+
+    let mut tx = client.start_transaction()?;
+    let value = bucket.read_registered(&mut tx, &Key("count:visits".as_bytes().to_vec()))?;
+    let unknown = bucket.read_registered(&mut tx, &Key("visits".as_bytes().to_vec())).err().unwrap();
+    tx.commit()?;
+
+    // asserts
+    match value {
+        CRDTValue::Counter(v) => assert_eq!(COUNTER_VALUE, v),
+        v => panic!("expected a counter, got {:?}", v),
+    }
+    assert_eq!(ErrorKind::InvalidInput, unknown.kind());
+    assert!(unknown.to_string().contains("no registered type for key"));
+    // exact keys first, then the longest prefix
+    assert_eq!(CRDT_type::LWWREG, bucket.registered_type(&Key("count:reg".as_bytes().to_vec()))?);
+    assert_eq!(CRDT_type::ORSET, bucket.registered_type(&Key("count:set:a".as_bytes().to_vec()))?);
+    assert_eq!(CRDT_type::COUNTER, bucket.registered_type(&Key("count:regs".as_bytes().to_vec()))?);
+    Ok(())
+}
+
+#[test]
+fn test_unsupported_crdt_type() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let types = TypeRegistry::new().key("limit".as_bytes().to_vec(), CRDT_type::BCOUNTER);
+    let bucket = Bucket::new("bucket".as_bytes().to_vec()).with_types(Arc::new(types));
+
+    let mut tx = client.start_transaction()?;
+    let bounded = bucket.read_registered(&mut tx, &Key("limit".as_bytes().to_vec())).err().unwrap();
+    tx.commit()?;
+    // a value in a field added by a newer Antidote version
+    let mut tx = NewerTypeTransaction;
+    let newer = MultiRead::new().add(&bucket, &Key("newer".as_bytes().to_vec()), CRDT_type::FLAG_DW).read_typed(&mut tx).err().unwrap();
+
+    // asserts
+    assert_eq!(ErrorKind::Unsupported, bounded.kind());
+    assert!(matches!(bounded, AntidoteError::UnsupportedCrdtType(CRDT_type::BCOUNTER)));
+    assert!(matches!(newer, AntidoteError::UnsupportedCrdtType(CRDT_type::FLAG_DW)));
+    Ok(())
+}
+
+// Answers every read with an object holding its value in a field unknown to the client.
+struct NewerTypeTransaction;
+
+impl Transaction for NewerTypeTransaction {
+    fn read(&mut self, objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, AntidoteError> {
+        let mut resp = ApbReadObjectsResp::new();
+        resp.set_success(true);
+        for _ in objects {
+            let mut object = ApbReadObjectResp::new();
+            object.unknown_fields.add_varint(100, 1);
+            resp.mut_objects().push(object);
+        }
+        Ok(resp)
+    }
+    fn update(&mut self, _updates: &Vec<ApbUpdateOp>) -> Result<(), AntidoteError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_interrupted_read_discards_connection() -> Result<(), Error> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let connects = Arc::new(AtomicUsize::new(0));
+    let (interrupt_ref, connects_ref) = (interrupt.clone(), connects.clone());
+    let connector: Connector = Arc::new(move |_addr: &str| {
+        connects_ref.fetch_add(1, Ordering::SeqCst);
+        Ok(Box::new(MemoryTransport::interruptible(interrupt_ref.clone())) as Box<dyn Transport>)
+    });
+    let manager = AntidoteConnectionManager::with_connector(String::from("memory:1"), connector);
+    let pool = r2d2::Pool::builder().max_size(1).build(manager).unwrap();
+    let client = Client::with_existing_pools(vec!(Host::new("memory", 1)), vec!(pool))?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    interrupt.store(true, Ordering::SeqCst);
+    let interrupted = bucket.read_counter(&mut tx, &key).err().unwrap();
+    // the rest of the read response is still unread, the abort must not take it for its response
+    let abort = tx.abort().err().unwrap();
+    drop(tx);
+    let mut tx = client.start_transaction()?;
+    let value = bucket.read_counter(&mut tx, &key)?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(ErrorKind::Interrupted, interrupted.kind());
+    assert!(abort.to_string().contains("out of sync"));
+    assert_eq!(COUNTER_VALUE, value);
+    assert_eq!(2, connects.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_interrupted_read_keeps_host_healthy() -> Result<(), Error> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let interrupt_ref = interrupt.clone();
+    let connector: Connector = Arc::new(move |_addr: &str| {
+        Ok(Box::new(MemoryTransport::interruptible(interrupt_ref.clone())) as Box<dyn Transport>)
+    });
+    let client = ClientBuilder::new()
+        .host(Host::new("memory", 1))
+        .connector(connector)
+        .max_pool_size(1)
+        .failure_threshold(1)
+        .quarantine_period(Duration::from_secs(60))
+        .build()?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    interrupt.store(true, Ordering::SeqCst);
+    assert!(bucket.read_counter(&mut tx, &key).is_err());
+    // the abort on drop fails, the connection is discarded
+    drop(tx);
+    interrupt.store(false, Ordering::SeqCst);
+    let mut tx = client.start_transaction()?;
+    let value = bucket.read_counter(&mut tx, &key)?;
+    tx.commit()?;
+
+    // asserts: the interrupted read did not quarantine the only host
+    assert_eq!(COUNTER_VALUE, value);
+    Ok(())
+}
+
+#[test]
+fn test_stream_desync_discards_connection() -> Result<(), Error> {
+    let connects = Arc::new(AtomicUsize::new(0));
+    let connects_ref = connects.clone();
+    let connector: Connector = Arc::new(move |_addr: &str| {
+        // only the first connection is off by one response
+        let transport = match connects_ref.fetch_add(1, Ordering::SeqCst) {
+            0 => MemoryTransport::with_stray_response(),
+            _ => MemoryTransport::new(),
+        };
+        Ok(Box::new(transport) as Box<dyn Transport>)
+    });
+    let manager = AntidoteConnectionManager::with_connector(String::from("memory:1"), connector);
+    let pool = r2d2::Pool::builder().max_size(1).build(manager).unwrap();
+    let client = Client::with_existing_pools(vec!(Host::new("memory", 1)), vec!(pool))?;
+
+    let desync = client.start_transaction().err().unwrap();
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidData, desync.kind());
+    assert!(matches!(desync, AntidoteError::UnexpectedMessageCode { expected: 124, .. }));
+    assert!(desync.to_string().contains("Expected 124"));
+    assert_eq!(2, connects.load(Ordering::SeqCst));
+    Ok(())
+}
+
+#[test]
+fn test_read_preferred_hosts() -> Result<(), Error> {
+    let primary = FakeAntidote::start(None);
+    let replica = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", replica.port).with_role(HostRole::ReadPreferred))
+        .host(Host::new("127.0.0.1", primary.port))
+        .max_pool_size(1)
+        .build()?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.transaction_builder().mode(TransactionMode::ReadOnly).start()?;
+    bucket.read_counter(&mut tx, &key)?;
+    tx.commit()?;
+    drop(tx);
+    bucket.read_counter(&mut client.create_static_transaction()?, &key)?;
+    let mut tx = client.start_transaction()?;
+    bucket.update(&mut tx, vec!(counter_inc(&key, 1)))?;
+    tx.commit()?;
+
+    // asserts
+    // read-only transaction (start, read, commit) and static read on the replica, the rest on the primary
+    assert_eq!(4, replica.requests());
+    assert_eq!(3, primary.requests());
+    Ok(())
+}
+
+#[test]
+fn test_read_preferred_host_fallback() -> Result<(), Error> {
+    let primary = FakeAntidote::start(None);
+    // port nobody listens on
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", port).with_role(HostRole::ReadPreferred))
+        .host(Host::new("127.0.0.1", primary.port))
+        .max_pool_size(1)
+        .checkout_deadline(Duration::from_millis(500))
+        .build()?;
+
+    let mut tx = client.transaction_builder().mode(TransactionMode::ReadOnly).start()?;
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(2, primary.requests());
+    Ok(())
+}
+
+#[test]
+fn test_panic_aborts_transaction() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = SharedClient::new(server.client());
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+
+    let worker = client.clone();
+    let result = thread::spawn(move || {
+        let mut tx = worker.start_transaction().unwrap();
+        bucket.update(&mut tx, vec!(set_add(&Key("keySet".as_bytes().to_vec()), vec!("A".as_bytes().to_vec())))).unwrap();
+        panic!("worker failed mid-transaction");
+    }).join();
+
+    // asserts
+    assert!(result.is_err());
+    // start, update and the abort sent when the transaction was dropped
+    assert_eq!(3, server.requests());
+    assert_eq!(0, client.in_use_connections());
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    assert_eq!(1, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_panic_discards_connection_on_failed_abort() -> Result<(), Error> {
+    // the server closes the connection after start and update, so the abort fails
+    let server = FakeAntidote::start(Some(2));
+    let client = SharedClient::new(server.client());
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+
+    let worker = client.clone();
+    let result = thread::spawn(move || {
+        let mut tx = worker.start_transaction().unwrap();
+        bucket.update(&mut tx, vec!(set_add(&Key("keySet".as_bytes().to_vec()), vec!("A".as_bytes().to_vec())))).unwrap();
+        panic!("worker failed mid-transaction");
+    }).join();
+
+    // asserts
+    assert!(result.is_err());
+    // the closed connection is not handed out again
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    assert_eq!(2, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_transaction_limit_fail_fast() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(2)
+        .max_open_transactions(1)
+        .transaction_limit_mode(TransactionLimitMode::FailFast)
+        .build()?;
+
+    let mut tx = client.start_transaction()?;
+    let err = client.start_transaction().err().unwrap();
+    let open = client.open_transactions();
+    tx.commit()?;
+    // dropping a transaction frees its slot as well
+    drop(client.start_transaction()?);
+    let mut tx = client.start_transaction()?;
+    tx.abort()?;
+
+    // asserts
+    assert_eq!(ErrorKind::WouldBlock, err.kind());
+    match &err {
+        AntidoteError::TransactionLimitReached(limit) => assert_eq!(1, *limit),
+        _ => panic!("expected TransactionLimitReached, got {}", err),
+    }
+    assert_eq!(1, open);
+    assert_eq!(0, client.open_transactions());
+    assert_eq!(ErrorKind::InvalidInput, ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_open_transactions(0)
+        .build().err().unwrap().kind());
+    Ok(())
+}
+
+#[test]
+fn test_transaction_limit_block() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = SharedClient::new(ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(2)
+        .max_open_transactions(1)
+        .checkout_deadline(Duration::from_secs(5))
+        .build()?);
+
+    let mut tx = client.start_transaction()?;
+    let waiting = client.clone();
+    let child = thread::spawn(move || -> Result<Duration, Error> {
+        let start = Instant::now();
+        let mut tx = waiting.start_transaction()?;
+        let waited = start.elapsed();
+        tx.commit()?;
+        Ok(waited)
+    });
+    thread::sleep(Duration::from_millis(200));
+    tx.commit()?;
+    let waited = child.join().unwrap()?;
+
+    // asserts
+    assert!(waited >= Duration::from_millis(150));
+    assert_eq!(0, client.open_transactions());
+    Ok(())
+}
+
+// Transaction recording the update operations instead of sending them.
+struct RecordingTransaction {
+    updates: Vec<ApbUpdateOp>,
+}
+
+impl Transaction for RecordingTransaction {
+    fn read(&mut self, _objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, AntidoteError> {
+        Ok(ApbReadObjectsResp::new())
+    }
+    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), AntidoteError> {
+        self.updates.extend(updates.iter().cloned());
+        Ok(())
+    }
+}
+
+// Transaction reading a multi-value register with the given values and recording the updates.
+struct MVRegTransaction {
+    values: Vec<Vec<u8>>,
+    updates: Vec<ApbUpdateOp>,
+}
+
+impl Transaction for MVRegTransaction {
+    fn read(&mut self, _objects: &Vec<ApbBoundObject>) -> Result<ApbReadObjectsResp, AntidoteError> {
+        let mut object = ApbReadObjectResp::new();
+        object.mut_mvreg().set_values(self.values.clone().into());
+        let mut resp = ApbReadObjectsResp::new();
+        resp.set_success(true);
+        resp.mut_objects().push(object);
+        Ok(resp)
+    }
+    fn update(&mut self, updates: &Vec<ApbUpdateOp>) -> Result<(), AntidoteError> {
+        self.updates.extend(updates.iter().cloned());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_read_mv_reg_repair() -> Result<(), Error> {
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("mvreg".as_bytes().to_vec());
+    let longest = |values: &[Vec<u8>]| values.iter().max_by_key(|v| v.len()).cloned().unwrap();
+
+    let mut siblings = MVRegTransaction { values: vec!("A".as_bytes().to_vec(), "BB".as_bytes().to_vec()), updates: Vec::new() };
+    let repaired = bucket.read_mv_reg_repair(&mut siblings, &key, longest)?;
+    let mut single = MVRegTransaction { values: vec!("A".as_bytes().to_vec()), updates: Vec::new() };
+    let kept = bucket.read_mv_reg_repair(&mut single, &key, |_: &[Vec<u8>]| -> Vec<u8> { panic!("resolver called without siblings") })?;
+
+    // asserts
+    assert_eq!("BB".as_bytes().to_vec(), repaired);
+    assert_eq!(1, siblings.updates.len());
+    assert_eq!("BB".as_bytes(), siblings.updates[0].get_operation().get_regop().get_value());
+    assert_eq!("A".as_bytes().to_vec(), kept);
+    assert!(single.updates.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_read_response_mismatch() {
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+    // answers every read without any object
+    let mut tx = RecordingTransaction { updates: Vec::new() };
+
+    let single = bucket.read_counter(&mut tx, &key).err().unwrap();
+    let batch = MultiRead::new()
+        .add(&bucket, &key, CRDT_type::COUNTER)
+        .add(&bucket, &Key("set".as_bytes().to_vec()), CRDT_type::ORSET)
+        .read(&mut tx).err().unwrap();
+
+    // asserts
+    assert_eq!(ErrorKind::InvalidData, single.kind());
+    assert!(matches!(single, AntidoteError::ProtocolMismatch { requested: 1, received: 0 }));
+    assert!(matches!(batch, AntidoteError::ProtocolMismatch { requested: 2, received: 0 }));
+}
+
+#[test]
+fn test_dedupe_and_merge() -> Result<(), Error> {
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let set = Key("set".as_bytes().to_vec());
+    let counter = Key("counter".as_bytes().to_vec());
+    let reg = Key("reg".as_bytes().to_vec());
+    let elems = |e: &[&str]| -> Vec<Vec<u8>> { e.iter().map(|s| s.as_bytes().to_vec()).collect() };
+
+    let mut tx = RecordingTransaction { updates: Vec::new() };
+    bucket.update(&mut tx, dedupe_and_merge(vec!(
+        set_add(&set, elems(&["A", "B"])),
+        counter_inc(&counter, 2),
+        set_add(&set, elems(&["B", "C"])),
+        counter_inc(&counter, -5),
+        set_remove(&set, elems(&["A"])),
+        set_add(&set, elems(&["D"])),
+        reg_put(&reg, elems(&["x"]).remove(0)),
+        reg_put(&reg, elems(&["y"]).remove(0)),
+    )))?;
+
+    // asserts
+    let ops: Vec<_> = tx.updates.iter().map(|u| u.get_operation()).collect();
+    assert_eq!(6, ops.len());
+    assert_eq!(elems(&["A", "B", "C"]), ops[0].get_setop().get_adds().to_vec());
+    assert_eq!(-3, ops[1].get_counterop().get_inc());
+    // the add after the remove stays after it
+    assert_eq!(elems(&["A"]), ops[2].get_setop().get_rems().to_vec());
+    assert_eq!(elems(&["D"]), ops[3].get_setop().get_adds().to_vec());
+    // register writes are not merged
+    assert!(ops[4].has_regop() && ops[5].has_regop());
+    Ok(())
+}
+
+#[test]
+fn test_update_iter() -> Result<(), Error> {
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+
+    let mut tx = RecordingTransaction { updates: Vec::new() };
+    bucket.update_iter(&mut tx, (0..3).map(|i| counter_inc(&Key(format!("counter{}", i).into_bytes()), i)))?;
+
+    // asserts
+    assert_eq!(3, tx.updates.len());
+    for (i, update) in tx.updates.iter().enumerate() {
+        assert_eq!(format!("counter{}", i).into_bytes(), update.get_boundobject().get_key());
+        assert_eq!("bucket".as_bytes(), update.get_boundobject().get_bucket());
+        assert_eq!(i as i64, update.get_operation().get_counterop().get_inc());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_seed() -> Result<(), Error> {
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let mut entry = ApbMapEntry::new();
+    entry.mut_key().set_key("visits".as_bytes().to_vec());
+    entry.mut_key().set_field_type(CRDT_type::FATCOUNTER);
+    entry.mut_value().mut_counter().set_value(7);
+    let mut map = MapReadResult { map_resp: ApbGetMapResp::new() };
+    map.map_resp.mut_entries().push(entry);
+
+    let mut tx = RecordingTransaction { updates: Vec::new() };
+    bucket.seed(&mut tx, vec!(
+        (Key("counter".as_bytes().to_vec()), CRDTValue::Counter(3)),
+        (Key("set".as_bytes().to_vec()), CRDTValue::Set(vec!("A".as_bytes().to_vec()))),
+        (Key("reg".as_bytes().to_vec()), CRDTValue::Reg("Hello".as_bytes().to_vec())),
+        (Key("map".as_bytes().to_vec()), CRDTValue::Map(map)),
+    ))?;
+    let flag = bucket.seed(&mut tx, vec!(
+        (Key("counter2".as_bytes().to_vec()), CRDTValue::Counter(1)),
+        (Key("flag".as_bytes().to_vec()), CRDTValue::Flag(true)),
+    )).err().unwrap();
+
+    // asserts
+    assert_eq!(4, tx.updates.len());
+    assert_eq!(3, tx.updates[0].get_operation().get_counterop().get_inc());
+    assert_eq!(CRDT_type::ORSET, tx.updates[1].get_boundobject().get_field_type());
+    assert_eq!("Hello".as_bytes(), tx.updates[2].get_operation().get_regop().get_value());
+    let nested = &tx.updates[3].get_operation().get_mapop().get_updates()[0];
+    assert_eq!(CRDT_type::FATCOUNTER, nested.get_key().get_field_type());
+    assert_eq!(7, nested.get_update().get_counterop().get_inc());
+    // nothing of the failed seed was sent
+    assert_eq!(ErrorKind::InvalidInput, flag.kind());
+    Ok(())
+}
+
+#[test]
+fn test_deep_entry_count() {
+    fn entry(key: &str, crdt_type: CRDT_type) -> ApbMapEntry {
+        let mut entry = ApbMapEntry::new();
+        entry.mut_key().set_key(key.as_bytes().to_vec());
+        entry.mut_key().set_field_type(crdt_type);
+        entry
+    }
+    // {counter, reg, inner: {set, empty: {}, innermost: {flag, mvreg}}}
+    let mut innermost = entry("innermost", CRDT_type::GMAP);
+    innermost.mut_value().mut_map().mut_entries().push(entry("flag", CRDT_type::FLAG_EW));
+    innermost.mut_value().mut_map().mut_entries().push(entry("mvreg", CRDT_type::MVREG));
+    let mut inner = entry("inner", CRDT_type::RRMAP);
+    inner.mut_value().mut_map().mut_entries().push(entry("set", CRDT_type::ORSET));
+    inner.mut_value().mut_map().mut_entries().push(entry("empty", CRDT_type::RRMAP));
+    inner.mut_value().mut_map().mut_entries().push(innermost);
+    let mut map = MapReadResult { map_resp: ApbGetMapResp::new() };
+    map.map_resp.mut_entries().push(entry("counter", CRDT_type::COUNTER));
+    map.map_resp.mut_entries().push(entry("reg", CRDT_type::LWWREG));
+    map.map_resp.mut_entries().push(inner);
+
+    // asserts
+    assert_eq!(5, map.deep_entry_count());
+    assert_eq!(0, MapReadResult { map_resp: ApbGetMapResp::new() }.deep_entry_count());
+}
+
+#[test]
+fn test_map_to_entries() {
+    let mut map = MapReadResult { map_resp: ApbGetMapResp::new() };
+    let mut counter = ApbMapEntry::new();
+    counter.mut_key().set_key("visits".as_bytes().to_vec());
+    counter.mut_key().set_field_type(CRDT_type::COUNTER);
+    counter.mut_value().mut_counter().set_value(3);
+    let mut inner = ApbMapEntry::new();
+    inner.mut_key().set_key("inner".as_bytes().to_vec());
+    inner.mut_key().set_field_type(CRDT_type::RRMAP);
+    inner.mut_value().mut_map().mut_entries().push(counter.clone());
+    let mut bounded = ApbMapEntry::new();
+    bounded.mut_key().set_key("bounded".as_bytes().to_vec());
+    bounded.mut_key().set_field_type(CRDT_type::BCOUNTER);
+    map.map_resp.mut_entries().push(inner);
+    map.map_resp.mut_entries().push(bounded);
+    map.map_resp.mut_entries().push(counter);
+
+    let entries = map.to_entries();
+
+    // asserts: in response order, bounded counters skipped, nested maps decoded the same way
+    let keys: Vec<MapEntryKey> = entries.iter().map(|(k, _)| k.clone()).collect();
+    assert_eq!(vec!(MapEntryKey { key: "inner".as_bytes().to_vec(), crdt_type: CRDT_type::RRMAP },
+        MapEntryKey { key: "visits".as_bytes().to_vec(), crdt_type: CRDT_type::COUNTER }), keys);
+    match &entries[0].1 {
+        CRDTValue::Map(nested) => assert!(matches!(nested.to_entries()[..], [(_, CRDTValue::Counter(3))])),
+        other => panic!("unexpected value {:?}", other),
+    }
+    assert!(matches!(entries[1].1, CRDTValue::Counter(3)));
+}
+
+#[test]
+fn test_map_entry_not_found() {
+    let mut map = MapReadResult { map_resp: ApbGetMapResp::new() };
+    let mut counter = ApbMapEntry::new();
+    counter.mut_key().set_key("visits".as_bytes().to_vec());
+    counter.mut_key().set_field_type(CRDT_type::COUNTER);
+    counter.mut_value().mut_counter().set_value(3);
+    map.map_resp.mut_entries().push(counter);
+
+    let other_key = map.counter(&Key("other".as_bytes().to_vec())).err().unwrap();
+    let other_type = map.set(&Key("visits".as_bytes().to_vec())).err().unwrap();
+    let many = map.get_many(&[(Key("visits".as_bytes().to_vec()), CRDT_type::LWWREG)]);
+
+    // asserts
+    assert_eq!(3, map.counter(&Key("visits".as_bytes().to_vec())).unwrap());
+    assert!(matches!(other_key, AntidoteError::EntryNotFound { ref key, crdt_type: CRDT_type::COUNTER } if key == b"other"));
+    assert_eq!(ErrorKind::NotFound, other_key.kind());
+    assert!(matches!(other_type, AntidoteError::EntryNotFound { crdt_type: CRDT_type::ORSET, .. }));
+    assert!(matches!(many[0], Err(AntidoteError::EntryNotFound { crdt_type: CRDT_type::LWWREG, .. })));
+}
+
+#[test]
+fn test_map_diff_to() -> Result<(), Error> {
+    fn entry(key: &str, crdt_type: CRDT_type) -> MapEntryKey {
+        MapEntryKey { key: key.as_bytes().to_vec(), crdt_type }
+    }
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("config".as_bytes().to_vec());
+    let mut actual = MapReadResult { map_resp: ApbGetMapResp::new() };
+    for (k, crdt_type) in [("visits", CRDT_type::COUNTER), ("tags", CRDT_type::ORSET), ("name", CRDT_type::LWWREG), ("old", CRDT_type::LWWREG)] {
+        let mut me = ApbMapEntry::new();
+        me.mut_key().set_key(k.as_bytes().to_vec());
+        me.mut_key().set_field_type(crdt_type);
+        match crdt_type {
+            CRDT_type::COUNTER => me.mut_value().mut_counter().set_value(3),
+            CRDT_type::ORSET => me.mut_value().mut_set().set_value(vec!("a".as_bytes().to_vec(), "b".as_bytes().to_vec()).into()),
+            _ => me.mut_value().mut_reg().set_value("x".as_bytes().to_vec()),
+        }
+        actual.map_resp.mut_entries().push(me);
+    }
+    let mut desired = DesiredMap::new();
+    desired.insert(entry("visits", CRDT_type::COUNTER), CRDTValue::Counter(5));
+    desired.insert(entry("tags", CRDT_type::ORSET), CRDTValue::Set(vec!("b".as_bytes().to_vec(), "c".as_bytes().to_vec())));
+    desired.insert(entry("name", CRDT_type::LWWREG), CRDTValue::Reg("x".as_bytes().to_vec()));
+    desired.insert(entry("enabled", CRDT_type::FLAG_EW), CRDTValue::Flag(true));
+
+    let updates = actual.diff_to(&key, MapKind::AddWins, &desired)?;
+    let mut tx = RecordingTransaction { updates: Vec::new() };
+    bucket.update(&mut tx, updates)?;
+    let mut unchanged = desired.clone();
+    unchanged.remove(&entry("enabled", CRDT_type::FLAG_EW));
+    unchanged.insert(entry("visits", CRDT_type::COUNTER), CRDTValue::Counter(3));
+    unchanged.insert(entry("tags", CRDT_type::ORSET), CRDTValue::Set(vec!("a".as_bytes().to_vec(), "b".as_bytes().to_vec())));
+    unchanged.insert(entry("old", CRDT_type::LWWREG), CRDTValue::Reg("x".as_bytes().to_vec()));
+    let grow_only = actual.diff_to(&key, MapKind::GrowOnly, &DesiredMap::new()).err().unwrap();
+
+    // asserts
+    assert_eq!(1, tx.updates.len());
+    let mapop = tx.updates[0].get_operation().get_mapop();
+    let nested = |k: &str| mapop.get_updates().iter().filter(|u| u.get_key().get_key() == k.as_bytes())
+        .map(|u| u.get_update().clone()).collect::<Vec<_>>();
+    assert_eq!(4, mapop.get_updates().len());
+    assert_eq!(2, nested("visits")[0].get_counterop().get_inc());
+    let tags = nested("tags");
+    assert_eq!(vec!("c".as_bytes().to_vec()), tags[0].get_setop().get_adds().to_vec());
+    assert_eq!(vec!("a".as_bytes().to_vec()), tags[1].get_setop().get_rems().to_vec());
+    assert!(nested("name").is_empty());
+    assert!(nested("enabled")[0].get_flagop().get_value());
+    assert_eq!(1, mapop.get_removedKeys().len());
+    assert_eq!("old".as_bytes(), mapop.get_removedKeys()[0].get_key());
+    assert!(actual.diff_to(&key, MapKind::AddWins, &unchanged)?.is_empty());
+    assert_eq!(ErrorKind::InvalidInput, grow_only.kind());
+    Ok(())
+}
+
+#[test]
+fn test_clone_update() -> Result<(), Error> {
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let increment = counter_inc(&Key("counter".as_bytes().to_vec()), 1);
+
+    let mut tx = RecordingTransaction { updates: Vec::new() };
+    for _ in 0..3 {
+        bucket.update(&mut tx, vec!(increment.clone()))?;
+    }
+
+    // asserts
+    assert_eq!(3, tx.updates.len());
+    assert!(tx.updates.iter().all(|u| u.get_operation().get_counterop().get_inc() == 1));
+    Ok(())
+}
+
+#[test]
+fn test_operation_timeout() -> Result<(), Error> {
+    // answers the start of a transaction, then never answers again
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut size = [0u8; 4];
+        stream.read_exact(&mut size).unwrap();
+        let mut request = vec![0u8; u32::from_be_bytes(size) as usize];
+        stream.read_exact(&mut request).unwrap();
+        let mut resp = ApbStartTransactionResp::new();
+        resp.set_success(true);
+        resp.set_transaction_descriptor("txid".as_bytes().to_vec());
+        common::write_frame(&mut stream, 124, &resp.write_to_bytes().unwrap()).unwrap();
+        while stream.read(&mut request).map(|n| n > 0).unwrap_or(false) {}
+    });
+    let client = ClientBuilder::new().host(Host::new("127.0.0.1", port)).max_pool_size(1).build()?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let start = Instant::now();
+    let timed_out = tx.with_timeout(Duration::from_millis(100), |tx| bucket.read_counter(tx, &key)).err().unwrap();
+    let elapsed = start.elapsed();
+
+    // asserts
+    assert!(timed_out.kind() == ErrorKind::WouldBlock || timed_out.kind() == ErrorKind::TimedOut, "{:?}", timed_out);
+    assert!(elapsed < Duration::from_secs(5));
+    Ok(())
+}
+
+#[test]
+fn test_operation_timeout_restored() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+
+    let mut tx = client.start_transaction()?;
+    let value = tx.with_timeout(Duration::from_millis(500), |tx| tx.with_timeout(Duration::from_millis(100), |tx| bucket.read_counter(tx, &key)))?;
+    tx.commit()?;
+    drop(tx);
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts: the timeouts were restored, so the connection went back to the pool and was used again
+    assert_eq!(COUNTER_VALUE, value);
+    assert_eq!(1, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_operation_timeout_restored_on_panic() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = server.client();
+
+    let mut tx = client.start_transaction()?;
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        tx.with_timeout(Duration::from_millis(100), |_| -> Result<(), AntidoteError> { panic!("operation panicked") })
+    }));
+    tx.commit()?;
+    drop(tx);
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts: the guard restored the timeouts while unwinding, the connection was used again
+    assert!(panicked.is_err());
+    assert_eq!(1, server.accepted());
+    Ok(())
+}
+
+#[test]
+fn test_operation_timeout_unsupported() -> Result<(), Error> {
+    let connector: Connector = Arc::new(|_addr: &str| Ok(Box::new(MemoryTransport::new()) as Box<dyn Transport>));
+    let manager = AntidoteConnectionManager::with_connector(String::from("memory:1"), connector);
+    let pool = r2d2::Pool::builder().max_size(1).build(manager).unwrap();
+    let client = Client::with_existing_pools(vec!(Host::new("memory", 1)), vec!(pool))?;
+
+    let mut tx = client.start_transaction()?;
+    let unsupported = tx.with_timeout(Duration::from_millis(100), |tx| tx.commit()).err().unwrap();
+    tx.commit()?;
+
+    // asserts
+    assert_eq!(ErrorKind::Unsupported, unsupported.kind());
+    Ok(())
+}
+
+#[test]
+fn test_pool_exhausted_fail_fast() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .pool_exhausted_mode(PoolExhaustedMode::FailFast)
+        .build()?;
+
+    let tx = client.start_transaction()?;
+    let start = Instant::now();
+    let exhausted = client.start_transaction().err().unwrap();
+    let elapsed = start.elapsed();
+    drop(tx);
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+
+    // asserts
+    assert!(matches!(exhausted, AntidoteError::PoolExhausted));
+    assert_eq!(ErrorKind::WouldBlock, exhausted.kind());
+    assert!(elapsed < Duration::from_secs(1));
+    Ok(())
+}
+
+#[test]
+fn test_pool_timeout() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .checkout_deadline(Duration::from_millis(200))
+        .build()?;
+    // a connector that cannot connect to anything
+    let connector: Connector = Arc::new(|addr: &str| Err(Error::new(ErrorKind::ConnectionRefused, format!("{} refused", addr))));
+    let manager = AntidoteConnectionManager::with_connector(String::from("memory:1"), connector);
+    let pool = r2d2::Pool::builder().max_size(1).connection_timeout(Duration::from_millis(200)).build_unchecked(manager);
+    let unreachable = ClientBuilder::new().host(Host::new("memory", 1)).checkout_deadline(Duration::from_millis(200)).build_with_pools(vec!(pool))?;
+
+    let tx = client.start_transaction()?;
+    let busy = client.start_transaction().err().unwrap();
+    drop(tx);
+    let dead = unreachable.start_transaction().err().unwrap();
+
+    // asserts
+    assert!(matches!(busy, AntidoteError::PoolTimeout(_)));
+    assert_eq!(ErrorKind::TimedOut, busy.kind());
+    match &dead {
+        AntidoteError::NoHealthyHosts(failures) => assert!(failures[0].1.contains("refused")),
+        _ => panic!("expected NoHealthyHosts, got {}", dead),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_connection_timeout() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .connection_timeout(Duration::from_millis(200))
+        .checkout_deadline(Duration::from_secs(10))
+        .build()?;
+
+    let tx = client.start_transaction()?;
+    let start = Instant::now();
+    let busy = client.start_transaction().err().unwrap();
+    let elapsed = start.elapsed();
+    drop(tx);
+
+    // asserts: the checkout gives up after the connection timeout, long before the deadline
+    match &busy {
+        AntidoteError::PoolTimeout(waited) => assert!(*waited >= Duration::from_millis(200)),
+        _ => panic!("expected PoolTimeout, got {}", busy),
+    }
+    assert!(elapsed >= Duration::from_millis(200));
+    assert!(elapsed < Duration::from_secs(2));
+    Ok(())
+}
+
+#[test]
+fn test_pool_exhausted_overflow() -> Result<(), Error> {
+    let server = FakeAntidote::start(None);
+    let client = ClientBuilder::new()
+        .host(Host::new("127.0.0.1", server.port))
+        .max_pool_size(1)
+        .connection_timeout(Duration::from_millis(200))
+        .checkout_deadline(Duration::from_millis(200))
+        .pool_exhausted_mode(PoolExhaustedMode::Overflow(1))
+        .build()?;
+
+    let first = client.start_transaction()?;
+    let mut overflow = client.start_transaction()?;
+    overflow.commit()?;
+    // the overflow connection is still in use
+    let waited = client.start_transaction().err().unwrap();
+    drop(overflow);
+    let mut tx = client.start_transaction()?;
+    tx.commit()?;
+    drop(first);
+
+    // asserts
+    assert!(matches!(waited, AntidoteError::PoolTimeout(_)));
+    // the overflow connection was closed after use and opened again for the last transaction
+    assert_eq!(3, server.accepted());
+    Ok(())
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::io::Error;
+use std::sync::{Arc, Mutex};
+
+use metrics::{Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key as MetricKey, KeyName, Metadata, Recorder, SharedString, Unit};
+
+use common::MemoryTransport;
+use antidote_rust_client::{ClientBuilder, Connector, Host, Transport};
+use antidote_rust_client::transactions::{Bucket, CRDTUpdater, Key, counter_inc};
+
+
+// Recorder keeping the name of each metric every time it is incremented or recorded, with its labels.
+#[derive(Default)]
+struct MemoryRecorder {
+    recorded: Arc<Mutex<Vec<String>>>,
+}
+
+struct Handle {
+    name: String,
+    recorded: Arc<Mutex<Vec<String>>>,
+}
+
+impl CounterFn for Handle {
+    fn increment(&self, _value: u64) {
+        self.recorded.lock().unwrap().push(self.name.clone());
+    }
+    fn absolute(&self, _value: u64) {}
+}
+
+impl GaugeFn for Handle {
+    fn increment(&self, _value: f64) {}
+    fn decrement(&self, _value: f64) {}
+    fn set(&self, _value: f64) {}
+}
+
+impl HistogramFn for Handle {
+    fn record(&self, _value: f64) {
+        self.recorded.lock().unwrap().push(self.name.clone());
+    }
+}
+
+impl MemoryRecorder {
+    fn handle(&self, key: &MetricKey) -> Arc<Handle> {
+        let labels: Vec<String> = key.labels().map(|l| format!("{}={}", l.key(), l.value())).collect();
+        let name = if labels.is_empty() { key.name().to_string() } else { format!("{}{{{}}}", key.name(), labels.join(",")) };
+        Arc::new(Handle { name, recorded: self.recorded.clone() })
+    }
+
+    fn count(&self, name: &str) -> usize {
+        self.recorded.lock().unwrap().iter().filter(|n| *n == name).count()
+    }
+}
+
+impl Recorder for MemoryRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+    fn register_counter(&self, key: &MetricKey, _metadata: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.handle(key))
+    }
+    fn register_gauge(&self, key: &MetricKey, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(self.handle(key))
+    }
+    fn register_histogram(&self, key: &MetricKey, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(self.handle(key))
+    }
+}
+
+#[test]
+fn test_metrics_observer() -> Result<(), Error> {
+    // setup: the builder installs the MetricsObserver by default with the feature enabled
+    let connector: Connector = Arc::new(|_addr: &str| Ok(Box::new(MemoryTransport::new()) as Box<dyn Transport>));
+    let client = ClientBuilder::new().host(Host::new("memory", 1)).connector(connector).max_pool_size(1).build()?;
+    let bucket = Bucket::new("bucket".as_bytes().to_vec());
+    let key = Key("counter".as_bytes().to_vec());
+    let recorder = MemoryRecorder::default();
+
+    metrics::with_local_recorder(&recorder, || -> Result<(), Error> {
+        let mut tx = client.start_transaction()?;
+        bucket.update(&mut tx, vec!(counter_inc(&key, 1)))?;
+        tx.commit()?;
+        // returns the connection to the pool of size 1
+        drop(tx);
+        let mut tx = client.start_transaction()?;
+        Ok(tx.abort()?)
+    })?;
+
+    // asserts
+    assert_eq!(2, recorder.count("antidote_client_transactions_started_total"));
+    assert_eq!(1, recorder.count("antidote_client_transactions_committed_total"));
+    assert_eq!(1, recorder.count("antidote_client_transactions_aborted_total"));
+    assert_eq!(2, recorder.count("antidote_client_pool_checkout_duration_seconds"));
+    assert_eq!(1, recorder.count("antidote_client_operation_duration_seconds{operation=update,success=true}"));
+    assert_eq!(1, recorder.count("antidote_client_operation_duration_seconds{operation=commit,success=true}"));
+    Ok(())
+}